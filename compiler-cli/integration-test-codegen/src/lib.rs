@@ -68,6 +68,42 @@ pub fn gen_ast_idempotence_integration_tests(_args: TokenStream) -> TokenStream
     )
 }
 
+#[allow(clippy::needless_pass_by_value)] // rust-clippy/issues/3067
+#[proc_macro]
+pub fn gen_semantic_integration_tests(_args: TokenStream) -> TokenStream {
+    gen_integration_tests(
+        &quote! { CompilerPhase::Semantic },
+        "semantic",
+        |v| quote! { #v },
+        "",
+        true,
+    )
+}
+
+#[allow(clippy::needless_pass_by_value)] // rust-clippy/issues/3067
+#[proc_macro]
+pub fn gen_interpreter_integration_tests(_args: TokenStream) -> TokenStream {
+    gen_integration_tests(
+        &quote! { CompilerPhase::Interpreter },
+        "interpreter",
+        |v| quote! { #v },
+        "",
+        true,
+    )
+}
+
+#[allow(clippy::needless_pass_by_value)] // rust-clippy/issues/3067
+#[proc_macro]
+pub fn gen_binary_integration_tests(_args: TokenStream) -> TokenStream {
+    gen_integration_tests(
+        &quote! { CompilerPhase::Binary },
+        "binary",
+        |v| quote! { #v },
+        "",
+        true,
+    )
+}
+
 fn gen_integration_tests<F>(
     phase: &proc_macro2::TokenStream,
     subfolder: &str,
@@ -11,9 +11,14 @@
 #![feature(custom_attribute)]
 
 use compiler_lib::{
-    asciifile, ast,
+    asciifile, ast, backend,
     context::{self, Context},
+    diagnostics::{Diagnostics, ErrorFormat, SnippetOptions},
+    error_codes::{self, ErrorCode},
+    interpreter, ir,
     lexer::{Lexer, TokenKind},
+    lints::{LintLevel, LintSelector},
+    optimization,
     parser::Parser,
     print::{self, lextest},
     sem,
@@ -21,7 +26,7 @@ use compiler_lib::{
 };
 use failure::{Error, Fail, ResultExt};
 use memmap::Mmap;
-use std::{fs::File, io, path::PathBuf, process::exit};
+use std::{fs::File, io, path::PathBuf, process::exit, thread};
 use structopt::StructOpt;
 use termcolor::{ColorChoice, StandardStream};
 
@@ -48,10 +53,86 @@ enum CliError {
     Ascii { path: PathBuf },
     #[fail(display = "cannot copy input file {:?} to stdout", input)]
     Echo { input: PathBuf },
+    #[fail(display = "no explanation for error code {}", code)]
+    UnknownErrorCode { code: ErrorCode },
 }
 
 #[derive(StructOpt)]
 #[structopt(name = "comprakt")]
+struct CliOptions {
+    #[structopt(flatten)]
+    diagnostics: DiagnosticsOptions,
+    #[structopt(subcommand)]
+    command: CliCommand,
+}
+
+/// Options for the output of errors and warnings, shared by all commands
+#[derive(StructOpt, Clone)]
+struct DiagnosticsOptions {
+    /// The format of errors and warnings: `human`, or `json` for one JSON
+    /// object per message and line
+    #[structopt(long = "error-format", default_value = "human", raw(global = "true"))]
+    error_format: ErrorFormat,
+    /// The number of source lines shown above and below the lines of an
+    /// error or warning
+    #[structopt(long = "context-lines", default_value = "0", raw(global = "true"))]
+    context_lines: usize,
+    /// Silence the warnings of LINT, or all warnings for `warnings`
+    #[structopt(
+        short = "A",
+        long = "allow",
+        raw(value_name = r#""LINT""#, number_of_values = "1", global = "true")
+    )]
+    allow: Vec<LintSelector>,
+    /// Report the warnings of LINT, or all warnings for `warnings`
+    #[structopt(
+        short = "W",
+        long = "warn",
+        raw(value_name = r#""LINT""#, number_of_values = "1", global = "true")
+    )]
+    warn: Vec<LintSelector>,
+    /// Report the warnings of LINT as errors, or all warnings for `warnings`
+    #[structopt(
+        short = "D",
+        long = "deny",
+        raw(value_name = r#""LINT""#, number_of_values = "1", global = "true")
+    )]
+    deny: Vec<LintSelector>,
+    /// Report all warnings as errors, the same as `-D warnings`
+    #[structopt(long = "deny-warnings", raw(global = "true"))]
+    deny_warnings: bool,
+}
+
+impl DiagnosticsOptions {
+    fn apply(&self, diagnostics: &mut Diagnostics) {
+        diagnostics.set_format(self.error_format);
+        diagnostics.set_snippet_options(SnippetOptions {
+            context_lines: self.context_lines,
+            ..SnippetOptions::default()
+        });
+
+        // the flags are applied from the most lenient to the strictest one,
+        // so the strictest level wins if the same lint, or `warnings`, is
+        // given to multiple flags. A level for a single lint always wins over
+        // the level for all warnings, e.g. `-A foo --deny-warnings` allows
+        // `foo`.
+        let levels = [
+            (&self.allow, LintLevel::Allow),
+            (&self.warn, LintLevel::Warn),
+            (&self.deny, LintLevel::Deny),
+        ];
+        for (lints, level) in &levels {
+            for lint in lints.iter() {
+                diagnostics.set_lint_level(*lint, *level);
+            }
+        }
+        if self.deny_warnings {
+            diagnostics.set_lint_level(LintSelector::All, LintLevel::Deny);
+        }
+    }
+}
+
+#[derive(StructOpt)]
 enum CliCommand {
     #[structopt(name = "--echo")]
     /// Writes the input file to stdout without modification
@@ -87,24 +168,94 @@ enum CliCommand {
         #[structopt(name = "FILE", parse(from_os_str))]
         path: PathBuf,
     },
+    /// Check the input file and execute it without compiling it
+    #[structopt(name = "--interpret")]
+    Interpret {
+        #[structopt(name = "FILE", parse(from_os_str))]
+        path: PathBuf,
+    },
+    /// Explain an error code, e.g. E0101, with an example
+    #[structopt(name = "--explain")]
+    Explain {
+        #[structopt(name = "CODE")]
+        code: ErrorCode,
+    },
+    /// Compile the input file to a native executable
+    #[structopt(name = "--compile")]
+    Compile {
+        #[structopt(name = "FILE", parse(from_os_str))]
+        path: PathBuf,
+        /// The path of the generated executable
+        #[structopt(
+            short = "o",
+            long = "output",
+            default_value = "a.out",
+            parse(from_os_str)
+        )]
+        output: PathBuf,
+        /// Write the control flow graph of every method in the DOT format
+        /// to DIR, before any optimization
+        #[structopt(long = "dump-ir-dot", name = "DIR", parse(from_os_str))]
+        dump_ir_dot: Option<PathBuf>,
+        /// Inline methods with at most SIZE instructions. Methods that are
+        /// called from a single place are always inlined
+        #[structopt(long = "inline-threshold", name = "SIZE", default_value = "20")]
+        inline_threshold: usize,
+        /// Do not check for `null` references and array indices out of
+        /// bounds at run time
+        #[structopt(long = "no-runtime-checks")]
+        no_runtime_checks: bool,
+    },
 }
 
 fn main() {
-    let cmd = CliCommand::from_args();
+    let options = CliOptions::from_args();
 
-    if let Err(msg) = run_compiler(&cmd) {
+    if let Err(msg) = run_compiler(&options) {
         exit_with_error(&msg);
     }
 }
 
-fn run_compiler(cmd: &CliCommand) -> Result<(), Error> {
-    match cmd {
+fn run_compiler(options: &CliOptions) -> Result<(), Error> {
+    let diagnostics = &options.diagnostics;
+    match &options.command {
         CliCommand::Echo { path } => cmd_echo(path),
-        CliCommand::LexerTest { path } => cmd_lextest(path),
-        CliCommand::ParserTest { path } => cmd_parsetest(path),
-        CliCommand::PrintAst { path } => cmd_printast(path, &print::pretty::print),
-        CliCommand::DebugDumpAst { path } => cmd_printast(path, &print::structure::print),
-        CliCommand::Check { path } => cmd_check(path, &sem::check),
+        CliCommand::Explain { code } => cmd_explain(*code),
+        CliCommand::LexerTest { path } => cmd_lextest(path, diagnostics),
+        CliCommand::ParserTest { path } => cmd_parsetest(path, diagnostics),
+        CliCommand::PrintAst { path } => cmd_printast(path, diagnostics, &print::pretty::print),
+        CliCommand::DebugDumpAst { path } => {
+            cmd_printast(path, diagnostics, &print::structure::print)
+        }
+        CliCommand::Check { path } => cmd_check(path, diagnostics, &sem::check),
+        CliCommand::Interpret { path } => {
+            let path = path.clone();
+            let diagnostics = diagnostics.clone();
+            thread::Builder::new()
+                .stack_size(interpreter::STACK_SIZE)
+                .spawn(move || cmd_interpret(&path, &diagnostics))?
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        }
+        CliCommand::Compile {
+            path,
+            output,
+            dump_ir_dot,
+            inline_threshold,
+            no_runtime_checks,
+        } => {
+            let options = optimization::Options {
+                inline_threshold: *inline_threshold,
+            };
+            cmd_compile(
+                path,
+                diagnostics,
+                output,
+                dump_ir_dot.as_ref(),
+                !no_runtime_checks,
+                &options,
+            )
+        }
     }
 }
 
@@ -136,8 +287,14 @@ fn cmd_echo(path: &PathBuf) -> Result<(), Error> {
     Ok(())
 }
 
+fn cmd_explain(code: ErrorCode) -> Result<(), Error> {
+    let explanation = error_codes::explain(code).ok_or(CliError::UnknownErrorCode { code })?;
+    print!("{}", explanation);
+    Ok(())
+}
+
 macro_rules! setup_io {
-    (let $context:ident = $path:expr) => {
+    (let $context:ident = $path:expr, $diagnostics:expr) => {
         let path: &PathBuf = $path;
         let file = File::open(&path).context(CliError::OpenInput { path: path.clone() })?;
         let mmres = unsafe { Mmap::map(&file) };
@@ -163,115 +320,154 @@ macro_rules! setup_io {
             asciifile::AsciiFile::new(&bytes).context(CliError::Ascii { path: path.clone() })?;
 
         let stderr = StandardStream::stderr(ColorChoice::Auto);
-        let $context = Context::new(&ascii_file, box stderr);
+        let mut $context = Context::new(&ascii_file, box stderr);
+        $diagnostics.apply(&mut $context.diagnostics);
     };
 }
 
-fn cmd_check<C>(path: &PathBuf, checker: &C) -> Result<(), Error>
-where
-    C: Fn(&ast::AST<'_>, &context::Context<'_>) -> Result<(), Error>,
-{
-    setup_io!(let context = path);
-    let strtab = StringTable::new();
-    let lexer = Lexer::new(&strtab, &context);
+/// Lex and parse the input file. Lexical and syntax errors are reported,
+/// and the compiler exits if there were any.
+fn parse_or_exit<'f>(strtab: &'f StringTable, context: &'f Context<'f>) -> ast::AST<'f> {
+    let lexer = Lexer::new(strtab, context);
 
-    // adapt lexer to fail on first error
+    // report lexical errors and skip the offending input
     // filter whitespace and comments
-    let unforgiving_lexer = lexer.filter_map(|result| match result {
+    let forgiving_lexer = lexer.filter_map(|result| match result {
         Ok(token) => match token.data {
             TokenKind::Whitespace | TokenKind::Comment(_) => None,
             _ => Some(token),
         },
         Err(lexical_error) => {
-            context.diagnostics.error(&lexical_error);
-            context.diagnostics.write_statistics();
-            exit(1);
+            context.diagnostics.coded_error(&lexical_error);
+            None
         }
     });
 
-    let mut parser = Parser::new(unforgiving_lexer);
-
-    let program = match parser.parse() {
-        Ok(p) => p,
-        Err(parser_error) => {
-            context.diagnostics.error(&parser_error);
-            context.diagnostics.write_statistics();
-            exit(1);
-        }
-    };
-
-    checker(&program, &context)
+    let program = Parser::new(forgiving_lexer).parse_with_diagnostics(&context.diagnostics);
+    if context.diagnostics.errored() {
+        context.diagnostics.write_statistics();
+        exit(1);
+    }
+    program
 }
 
-fn cmd_printast<P>(path: &PathBuf, printer: &P) -> Result<(), Error>
+fn cmd_check<C>(path: &PathBuf, diagnostics: &DiagnosticsOptions, checker: &C) -> Result<(), Error>
 where
-    P: Fn(&ast::AST<'_>, &mut dyn std::io::Write) -> Result<(), Error>,
+    C: Fn(&ast::AST<'_>, &context::Context<'_>) -> Result<(), Error>,
 {
-    setup_io!(let context = path);
+    setup_io!(let context = path, diagnostics);
     let strtab = StringTable::new();
-    let lexer = Lexer::new(&strtab, &context);
+    let program = parse_or_exit(&strtab, &context);
 
-    // adapt lexer to fail on first error
-    // filter whitespace and comments
-    let unforgiving_lexer = lexer.filter_map(|result| match result {
-        Ok(token) => match token.data {
-            TokenKind::Whitespace | TokenKind::Comment(_) => None,
-            _ => Some(token),
-        },
-        Err(lexical_error) => {
-            context.diagnostics.error(&lexical_error);
-            context.diagnostics.write_statistics();
-            exit(1);
-        }
-    });
+    checker(&program, &context)?;
+
+    if context.diagnostics.errored() {
+        context.diagnostics.write_statistics();
+        exit(1);
+    }
 
-    let mut parser = Parser::new(unforgiving_lexer);
+    Ok(())
+}
+
+fn cmd_interpret(path: &PathBuf, diagnostics: &DiagnosticsOptions) -> Result<(), Error> {
+    setup_io!(let context = path, diagnostics);
+    let strtab = StringTable::new();
+    let program = parse_or_exit(&strtab, &context);
 
-    let program = match parser.parse() {
-        Ok(p) => p,
-        Err(parser_error) => {
-            context.diagnostics.error(&parser_error);
+    let program = match program {
+        ast::AST::Program(program) => program,
+        ast::AST::Empty => {
+            sem::check(&ast::AST::Empty, &context)?;
             context.diagnostics.write_statistics();
             exit(1);
         }
     };
 
-    printer(&program, &mut std::io::stdout())
+    let typed_program = sem::analyze(&program, &context);
+    if context.diagnostics.errored() {
+        context.diagnostics.write_statistics();
+        exit(1);
+    }
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    interpreter::run(
+        &typed_program,
+        &context,
+        &mut stdin.lock(),
+        &mut io::BufWriter::new(stdout.lock()),
+    );
+
+    if context.diagnostics.errored() {
+        context.diagnostics.write_statistics();
+        exit(1);
+    }
+
+    Ok(())
 }
 
-fn cmd_parsetest(path: &PathBuf) -> Result<(), Error> {
-    setup_io!(let context = path);
+fn cmd_compile(
+    path: &PathBuf,
+    diagnostics: &DiagnosticsOptions,
+    output: &PathBuf,
+    dump_ir_dot: Option<&PathBuf>,
+    runtime_checks: bool,
+    options: &optimization::Options,
+) -> Result<(), Error> {
+    setup_io!(let context = path, diagnostics);
     let strtab = StringTable::new();
-    let lexer = Lexer::new(&strtab, &context);
+    let program = parse_or_exit(&strtab, &context);
 
-    // adapt lexer to fail on first error
-    // filter whitespace and comments
-    let unforgiving_lexer = lexer.filter_map(|result| match result {
-        Ok(token) => match token.data {
-            TokenKind::Whitespace | TokenKind::Comment(_) => None,
-            _ => Some(token),
-        },
-        Err(lexical_error) => {
-            context.diagnostics.error(&lexical_error);
+    let program = match program {
+        ast::AST::Program(program) => program,
+        ast::AST::Empty => {
+            sem::check(&ast::AST::Empty, &context)?;
             context.diagnostics.write_statistics();
             exit(1);
         }
-    });
+    };
 
-    let mut parser = Parser::new(unforgiving_lexer);
+    let typed_program = sem::analyze(&program, &context);
+    if context.diagnostics.errored() {
+        context.diagnostics.write_statistics();
+        exit(1);
+    }
 
-    match parser.parse() {
-        Ok(_) => Ok(()),
-        Err(parser_error) => {
-            context.diagnostics.error(&parser_error);
-            context.diagnostics.write_statistics();
-            exit(1);
-        }
+    let mut program = ir::lower(&typed_program, runtime_checks);
+    // dump the IR as lowered, the optimizations remove inlined methods
+    if let Some(dir) = dump_ir_dot {
+        ir::dot::dump(&program, dir)?;
     }
+    optimization::optimize(&mut program, options);
+
+    backend::compile(&program, output)
+}
+
+fn cmd_printast<P>(
+    path: &PathBuf,
+    diagnostics: &DiagnosticsOptions,
+    printer: &P,
+) -> Result<(), Error>
+where
+    P: Fn(&ast::AST<'_>, &mut dyn std::io::Write) -> Result<(), Error>,
+{
+    setup_io!(let context = path, diagnostics);
+    let strtab = StringTable::new();
+    let program = parse_or_exit(&strtab, &context);
+
+    printer(&program, &mut std::io::stdout())
+}
+
+fn cmd_parsetest(path: &PathBuf, diagnostics: &DiagnosticsOptions) -> Result<(), Error> {
+    setup_io!(let context = path, diagnostics);
+    let strtab = StringTable::new();
+    parse_or_exit(&strtab, &context);
+
+    Ok(())
 }
 
-fn cmd_lextest(path: &PathBuf) -> Result<(), Error> {
-    setup_io!(let context = path);
+fn cmd_lextest(path: &PathBuf, diagnostics: &DiagnosticsOptions) -> Result<(), Error> {
+    setup_io!(let context = path, diagnostics);
     let strtab = StringTable::new();
     let lexer = Lexer::new(&strtab, &context);
 
@@ -279,15 +475,14 @@ fn cmd_lextest(path: &PathBuf) -> Result<(), Error> {
 
     for token in lexer {
         match token {
-            Err(lexical_error) => context.diagnostics.error(&lexical_error),
+            Err(lexical_error) => context.diagnostics.coded_error(&lexical_error),
             Ok(token) => write_token(&mut stdout, &token.data)?,
         }
+    }
 
-        // stop compilation on first error during lexing phase
-        if context.diagnostics.errored() {
-            context.diagnostics.write_statistics();
-            exit(1);
-        }
+    if context.diagnostics.errored() {
+        context.diagnostics.write_statistics();
+        exit(1);
     }
 
     write_eof_token(&mut stdout)?;
@@ -309,3 +504,44 @@ fn write_eof_token<O: io::Write>(out: &mut O) -> Result<(), Error> {
     writeln!(out, "EOF")?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compiler_lib::lints;
+
+    fn lint_level(args: &[&str], lint: &lints::Lint) -> LintLevel {
+        let args = ["comprakt"]
+            .iter()
+            .chain(args)
+            .chain(&["--check", "input.mj"]);
+        let options = CliOptions::from_iter(args);
+        let mut diagnostics = Diagnostics::new(box termcolor::Buffer::no_color());
+        options.diagnostics.apply(&mut diagnostics);
+        diagnostics.lint_level(lint)
+    }
+
+    #[test]
+    fn lint_flags_set_lint_levels() {
+        let unreachable = &lints::UNREACHABLE_STATEMENT;
+        assert_eq!(lint_level(&[], unreachable), LintLevel::Warn);
+        assert_eq!(lint_level(&["-D", "warnings"], unreachable), LintLevel::Deny);
+        assert_eq!(
+            lint_level(&["-A", "unreachable_statement", "-D", "unreachable_statement"], unreachable),
+            LintLevel::Deny
+        );
+        // a level for a single lint wins over the level for all warnings
+        assert_eq!(
+            lint_level(&["-A", "unreachable_statement", "--deny-warnings"], unreachable),
+            LintLevel::Allow
+        );
+        assert_eq!(
+            lint_level(&["-A", "warnings", "-W", "unreachable_statement"], unreachable),
+            LintLevel::Warn
+        );
+        assert_eq!(
+            lint_level(&["--deny-warnings"], &lints::COMMENT_SEPARATOR_INSIDE_COMMENT),
+            LintLevel::Deny
+        );
+    }
+}
@@ -32,6 +32,10 @@ enum CompilerPhase {
     Lexer,
     Parser,
     Ast,
+    Semantic,
+    Interpreter,
+    /// Compile the program to a native executable and run it
+    Binary,
 }
 
 const ROOT_DIR: &str = env!("CARGO_MANIFEST_DIR");
@@ -41,11 +45,25 @@ fn compiler_flag(phase: CompilerPhase) -> &'static str {
         CompilerPhase::Lexer => "--lextest",
         CompilerPhase::Parser => "--parsetest",
         CompilerPhase::Ast => "--print-ast",
+        CompilerPhase::Semantic => "--check",
+        CompilerPhase::Interpreter => "--interpret",
+        CompilerPhase::Binary => "--compile",
     }
 }
 
 fn compiler_call(phase: CompilerPhase, filepath: &PathBuf) -> Command {
-    let mut cmd = std::env::var("COMPILER_BINARY")
+    if let CompilerPhase::Binary = phase {
+        return compile_binary(filepath);
+    }
+
+    let mut cmd = compiler_binary();
+    cmd.env("TERM", "dumb"); // disable color output
+    cmd.args(&[OsStr::new(compiler_flag(phase)), filepath.as_os_str()]);
+    cmd
+}
+
+fn compiler_binary() -> Command {
+    std::env::var("COMPILER_BINARY")
         .map(|path| {
             println!("Test run using alternate compiler binary at {}", path);
             Command::new(path)
@@ -53,10 +71,27 @@ fn compiler_call(phase: CompilerPhase, filepath: &PathBuf) -> Command {
         .unwrap_or_else(|_| {
             println!("Test run using the default compiler binary");
             Command::main_binary().unwrap()
-        });
-    cmd.env("TERM", "dumb"); // disable color output
-    cmd.args(&[OsStr::new(compiler_flag(phase)), filepath.as_os_str()]);
-    cmd
+        })
+}
+
+/// Compile the input file and return a command that runs the executable.
+/// Compile errors are not part of the reference output, they fail the test.
+fn compile_binary(filepath: &PathBuf) -> Command {
+    let binary = std::env::temp_dir().join(with_extension(
+        &PathBuf::from(filepath.file_name().unwrap()),
+        ".out",
+    ));
+
+    let mut cmd = compiler_binary();
+    cmd.env("TERM", "dumb");
+    cmd.args(&[
+        OsStr::new(compiler_flag(CompilerPhase::Binary)),
+        filepath.as_os_str(),
+    ]);
+    cmd.arg("-o").arg(&binary);
+    cmd.assert().success();
+
+    Command::new(binary)
 }
 
 fn normalize_stderr(stderr: &str) -> String {
@@ -186,3 +221,6 @@ gen_lexer_integration_tests!();
 gen_parser_integration_tests!();
 gen_ast_reference_integration_tests!();
 gen_ast_idempotence_integration_tests!();
+gen_semantic_integration_tests!();
+gen_interpreter_integration_tests!();
+gen_binary_integration_tests!();
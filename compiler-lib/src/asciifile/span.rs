@@ -144,6 +144,19 @@ impl<'f> Span<'f> {
         LineIterator::new(self)
     }
 
+    /// The part of the span on its first line, without the line break.
+    pub fn first_line(&self) -> Span<'f> {
+        let line_end = self.start.get_line().end;
+        let line_end = match line_end.prev() {
+            Some(prev) if line_end.chr() == '\n' && line_end > self.start => prev,
+            _ => line_end,
+        };
+        Span {
+            start: self.start,
+            end: min(self.end, line_end),
+        }
+    }
+
     /// extends the span to include the given position
     pub fn extend_to_position(self, position: &Position<'f>) -> Span<'f> {
         Span::combine(&position.to_single_char_span(), &self)
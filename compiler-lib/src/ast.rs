@@ -99,6 +99,8 @@ pub struct Block<'t> {
 /// * `Return`: a return which can optionally return an expression
 /// * `LocalVariableDeclaration`: a declaration and optional initialization of
 /// a local variable
+/// * `Error`: a statement with a syntax error, only present in the partial
+/// AST of a program that failed to parse
 #[strum_discriminants(derive(Display))]
 #[derive(EnumDiscriminants, Debug, PartialEq, Eq, Clone)]
 pub enum Stmt<'t> {
@@ -117,6 +119,7 @@ pub enum Stmt<'t> {
         Symbol,
         Option<Box<Spanned<'t, Expr<'t>>>>,
     ),
+    Error,
 }
 
 /// An expression is either one of
@@ -162,7 +165,7 @@ pub enum Expr<'t> {
     // The old primary expressions
     Null,
     Boolean(bool),
-    Int(Symbol), // TODO Should be String?
+    Int(Symbol), // see `sem::IntLiterals` for the parsed value
     Var(Symbol),
     ThisMethodInvocation(Symbol, Spanned<'t, ArgumentList<'t>>),
     This,
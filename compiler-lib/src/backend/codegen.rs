@@ -0,0 +1,618 @@
+//! Translation of the IR to x86-64 assembly in AT&T syntax.
+//!
+//! Every IR value is kept in the register or stack slot assigned to it by
+//! `register_allocation`. Instructions load their operands into the scratch
+//! registers `%rax`, `%rcx` and `%rdx`, and store their result into the
+//! location of the value they define. All values occupy 8 bytes, `int`s are
+//! kept sign-extended to 64 bit and booleans are `0` or `1`. `Phi`s are
+//! implemented by copying the incoming values into the location of the
+//! `Phi` on every control flow edge.
+//!
+//! MiniJava methods use their own calling convention: the caller pushes the
+//! receiver (`this`) followed by the arguments from left to right, and
+//! removes them after the call. The callee addresses them relative to
+//! `%rbp`, and returns its result in `%rax`. Like in the System V calling
+//! convention, the callee preserves the callee-saved registers.
+//!
+//! `CheckNull` and `CheckBounds` call into the runtime to report a failed
+//! check, which does not return. The source line of the check is passed as a
+//! string constant in `.rodata`.
+//!
+//! `%rsp` is 16 byte aligned at every call: the frames are padded to a
+//! multiple of 16 bytes, and so are the arguments pushed for a call. Calls
+//! into the runtime follow the System V calling convention.
+//!
+//! The garbage collector of the runtime finds its roots on a shadow stack, a
+//! list of frame records linked through `mjrt_shadow_stack`. A method that
+//! holds references across a call, `NewObject` or `NewArray`, i.e. across
+//! any instruction that may collect garbage, pushes a record below its stack
+//! slots in the prologue and pops it before returning. Before each of these
+//! instructions, it copies the references that are live across it into the
+//! record and stores their number. Objects pass a map of the fields that
+//! hold references to the runtime, and arrays whether their elements are
+//! references, so the collector never mistakes an `int` for a reference.
+use super::register_allocation::{self, Allocation, Location, Register};
+use crate::{
+    ir::{
+        self, BinaryOp, BlockId, Constant, Instruction, SourceLine, Terminator, UnaryOp, ValueId,
+        ValueKind,
+    },
+    sem::BuiltinMethod,
+};
+use std::{collections::BTreeMap, fmt::Write, rc::Rc};
+
+/// The size of every value in objects, arrays and stack frames
+const VALUE_SIZE: i64 = 8;
+
+/// The entry point called by the runtime's `main` function
+const ENTRY_POINT: &str = "minijava_main";
+
+/// The variable of the runtime that points to the innermost frame record
+const SHADOW_STACK: &str = "mjrt_shadow_stack";
+
+macro_rules! emit {
+    ($gen:expr, $($arg:tt)*) => {
+        writeln!($gen.out, "\t{}", format_args!($($arg)*)).unwrap()
+    };
+}
+
+pub fn generate(program: &ir::Program) -> String {
+    let mut gen = CodeGenerator {
+        program,
+        out: String::new(),
+        next_label: 0,
+        source_lines: BTreeMap::new(),
+    };
+    gen.gen_program();
+    gen.out
+}
+
+struct CodeGenerator<'p> {
+    program: &'p ir::Program,
+    out: String,
+    next_label: usize,
+    /// The text of the source lines referenced by checks, by line number
+    source_lines: BTreeMap<usize, Rc<str>>,
+}
+
+/// The method that is currently generated.
+struct Frame<'p> {
+    method: &'p ir::Method,
+    allocation: Allocation,
+    /// The kind of every value of `method`, indexed by `ValueId`
+    kinds: Vec<ValueKind>,
+    /// The number of roots the frame record has room for. Methods that never
+    /// hold a reference across a collection have no frame record.
+    max_roots: usize,
+    /// The prefix of the labels of the blocks of `method`
+    label_prefix: String,
+}
+
+impl<'p> Frame<'p> {
+    /// The register or stack slot holding the value. The stack slots are
+    /// below the saved callee-saved registers.
+    fn location(&self, value: ValueId) -> String {
+        match self.allocation.locations[value.0] {
+            Some(Location::Register(register)) => register.to_string(),
+            Some(Location::Stack(slot)) => {
+                let offset = (self.allocation.callee_saved.len() + slot + 1) as i64 * VALUE_SIZE;
+                format!("{}(%rbp)", -offset)
+            }
+            None => panic!("{} has no location", value),
+        }
+    }
+
+    /// The location of a parameter pushed by the caller. `[rbp + 16]` is the
+    /// last argument, the receiver was pushed first.
+    fn param(&self, index: usize) -> String {
+        let offset = (2 + self.method.params.len() - index) as i64 * VALUE_SIZE;
+        format!("{}(%rbp)", offset)
+    }
+
+    /// The references that are live across an instruction that may collect
+    /// garbage. The collector has to find them in the frame record.
+    fn roots(&self, value: ValueId) -> Vec<ValueId> {
+        self.allocation.live_across_calls[&value]
+            .iter()
+            .cloned()
+            .filter(|root| self.kinds[root.0] == ValueKind::Reference)
+            .collect()
+    }
+
+    /// The size of the frame record, which is below the stack slots. It
+    /// holds the previous record, the number of roots and the roots.
+    fn record_size(&self) -> usize {
+        if self.max_roots > 0 {
+            2 + self.max_roots
+        } else {
+            0
+        }
+    }
+
+    /// The word of the frame record with the given index.
+    fn record_word(&self, index: usize) -> String {
+        let words = self.allocation.callee_saved.len() + self.allocation.stack_slots;
+        let offset = (words + self.record_size() - index) as i64 * VALUE_SIZE;
+        format!("{}(%rbp)", -offset)
+    }
+
+    fn block_label(&self, block: BlockId) -> String {
+        format!("{}{}", self.label_prefix, block.0)
+    }
+}
+
+impl<'p> CodeGenerator<'p> {
+    fn gen_program(&mut self) {
+        writeln!(self.out, "\t.text").unwrap();
+        for (i, method) in self.program.methods.iter().enumerate() {
+            if method.is_main {
+                self.gen_entry_point(method);
+            }
+            let mut frame = Frame {
+                method,
+                allocation: register_allocation::allocate(method),
+                kinds: self.program.value_kinds(method),
+                max_roots: 0,
+                label_prefix: format!(".LM{}_", i),
+            };
+            frame.max_roots = method
+                .blocks
+                .iter()
+                .flat_map(|block| &block.instructions)
+                .filter(|&&value| may_collect(method.value(value)))
+                .map(|&value| frame.roots(value).len())
+                .max()
+                .unwrap_or(0);
+            self.gen_method(&frame);
+        }
+        self.gen_reference_maps();
+        self.gen_source_lines();
+    }
+
+    /// The runtime finds the references in an object through the map of its
+    /// class: the number of fields that hold references, followed by their
+    /// indices.
+    fn gen_reference_maps(&mut self) {
+        if self.program.classes.is_empty() {
+            return;
+        }
+        writeln!(self.out, "\t.section .rodata").unwrap();
+        emit!(self, ".p2align 3");
+        let mut classes: Vec<_> = self.program.classes.values().collect();
+        classes.sort_by_key(|class| &class.name);
+        for class in classes {
+            let references: Vec<_> = class
+                .fields
+                .iter()
+                .enumerate()
+                .filter(|(_, field)| field.kind == ValueKind::Reference)
+                .map(|(index, _)| index)
+                .collect();
+            self.label(&reference_map_label(&class.name));
+            emit!(self, ".quad {}", references.len());
+            for index in references {
+                emit!(self, ".quad {}", index);
+            }
+        }
+    }
+
+    fn gen_source_lines(&mut self) {
+        if self.source_lines.is_empty() {
+            return;
+        }
+        writeln!(self.out, "\t.section .rodata").unwrap();
+        let source_lines = std::mem::replace(&mut self.source_lines, BTreeMap::new());
+        for (number, text) in source_lines {
+            self.label(&source_line_label(number));
+            emit!(self, ".asciz \"{}\"", escape_string(&text));
+        }
+    }
+
+    /// The runtime calls the main method through `ENTRY_POINT`.
+    fn gen_entry_point(&mut self, main: &ir::Method) {
+        writeln!(self.out, "\t.globl {}", ENTRY_POINT).unwrap();
+        writeln!(self.out, "{}:", ENTRY_POINT).unwrap();
+        // the return address misaligns the stack
+        emit!(self, "subq $8, %rsp");
+        // `this` and the unusable `String[]` parameter
+        emit!(self, "pushq $0");
+        emit!(self, "pushq $0");
+        emit!(self, "call {}", mangle(&main.class_name, &main.name));
+        emit!(self, "addq $24, %rsp");
+        emit!(self, "ret");
+    }
+
+    fn gen_method(&mut self, frame: &Frame<'_>) {
+        let method = frame.method;
+        let saved_size = frame.allocation.callee_saved.len() as i64 * VALUE_SIZE;
+        // keep the frame 16 byte aligned
+        let words = frame.allocation.stack_slots + frame.record_size();
+        let frame_size = saved_size + words as i64 * VALUE_SIZE;
+        let stack_size = (frame_size + 15) / 16 * 16 - saved_size;
+
+        writeln!(self.out, "{}:", mangle(&method.class_name, &method.name)).unwrap();
+        emit!(self, "pushq %rbp");
+        emit!(self, "movq %rsp, %rbp");
+        for register in &frame.allocation.callee_saved {
+            emit!(self, "pushq {}", register);
+        }
+        if stack_size > 0 {
+            emit!(self, "subq ${}, %rsp", stack_size);
+        }
+        if frame.max_roots > 0 {
+            emit!(self, "movq {}(%rip), %rax", SHADOW_STACK);
+            emit!(self, "movq %rax, {}", frame.record_word(0));
+            emit!(self, "leaq {}, %rax", frame.record_word(0));
+            emit!(self, "movq %rax, {}(%rip)", SHADOW_STACK);
+        }
+
+        // the entry block comes first, so the prologue falls through to it
+        for block in method.reverse_postorder() {
+            self.label(&frame.block_label(block));
+            for &value in &method.block(block).instructions {
+                self.gen_instruction(frame, value);
+            }
+            self.gen_terminator(frame, block);
+        }
+    }
+
+    /// Generate code that computes `value` and stores it in its location.
+    #[allow(clippy::cyclomatic_complexity)]
+    fn gen_instruction(&mut self, frame: &Frame<'_>, value: ValueId) {
+        if frame.max_roots > 0 && may_collect(frame.method.value(value)) {
+            self.gen_roots(frame, value);
+        }
+        match frame.method.value(value) {
+            // the incoming values are copied at the end of the predecessors
+            Instruction::Phi(_) => return,
+            Instruction::Param(index) => emit!(self, "movq {}, %rax", frame.param(*index)),
+            Instruction::Const(constant) => {
+                let constant = match constant {
+                    Constant::Int(value) => *value,
+                    Constant::Bool(value) => *value as i32,
+                    Constant::Null => 0,
+                };
+                emit!(self, "movq ${}, %rax", constant);
+            }
+            Instruction::Unary(op, operand) => {
+                emit!(self, "movq {}, %rax", frame.location(*operand));
+                match op {
+                    UnaryOp::Neg => {
+                        emit!(self, "negl %eax");
+                        emit!(self, "cltq");
+                    }
+                    UnaryOp::Not => emit!(self, "xorq $1, %rax"),
+                }
+            }
+            Instruction::Binary(op, lhs, rhs) => {
+                emit!(self, "movq {}, %rax", frame.location(*lhs));
+                emit!(self, "movq {}, %rcx", frame.location(*rhs));
+                self.gen_binary_op(*op);
+            }
+            Instruction::LoadField { object, field } => {
+                let offset = self.field_offset(field);
+                emit!(self, "movq {}, %rax", frame.location(*object));
+                emit!(self, "movq {}(%rax), %rax", offset);
+            }
+            Instruction::StoreField {
+                object,
+                field,
+                value,
+            } => {
+                let offset = self.field_offset(field);
+                emit!(self, "movq {}, %rcx", frame.location(*object));
+                emit!(self, "movq {}, %rax", frame.location(*value));
+                emit!(self, "movq %rax, {}(%rcx)", offset);
+                return;
+            }
+            Instruction::LoadElement { array, index, .. } => {
+                emit!(self, "movq {}, %rcx", frame.location(*array));
+                emit!(self, "movq {}, %rax", frame.location(*index));
+                emit!(self, "movq {}, %rax", element_address("%rax"));
+            }
+            Instruction::StoreElement {
+                array,
+                index,
+                value,
+            } => {
+                emit!(self, "movq {}, %rcx", frame.location(*array));
+                emit!(self, "movq {}, %rdx", frame.location(*index));
+                emit!(self, "movq {}, %rax", frame.location(*value));
+                emit!(self, "movq %rax, {}", element_address("%rdx"));
+                return;
+            }
+            Instruction::NewObject(class_name) => {
+                let size = self.program.classes[class_name].fields.len() as i64 * VALUE_SIZE;
+                emit!(self, "movq ${}, %rdi", size);
+                emit!(self, "leaq {}(%rip), %rsi", reference_map_label(class_name));
+                self.gen_runtime_call("mjrt_new");
+            }
+            Instruction::NewArray(length, elements) => {
+                let references = match elements {
+                    ValueKind::Primitive => 0,
+                    ValueKind::Reference => 1,
+                };
+                emit!(self, "movq {}, %rdi", frame.location(*length));
+                emit!(self, "movq ${}, %rsi", references);
+                self.gen_runtime_call("mjrt_new_array");
+            }
+            Instruction::Call {
+                class_name,
+                method_name,
+                args,
+            } => {
+                let padding = args.len() % 2;
+                if padding != 0 {
+                    emit!(self, "subq ${}, %rsp", VALUE_SIZE);
+                }
+                for arg in args {
+                    emit!(self, "pushq {}", frame.location(*arg));
+                }
+                emit!(self, "call {}", mangle(class_name, method_name));
+                let size = (args.len() + padding) as i64 * VALUE_SIZE;
+                emit!(self, "addq ${}, %rsp", size);
+            }
+            Instruction::CallBuiltin(method, args) => {
+                // all built-in methods take at most one argument
+                if let Some(arg) = args.first() {
+                    emit!(self, "movq {}, %rdi", frame.location(*arg));
+                }
+                self.gen_builtin_call(*method);
+            }
+            Instruction::CheckNull { object, line } => {
+                let ok_label = self.new_label();
+                emit!(self, "cmpq $0, {}", frame.location(*object));
+                emit!(self, "jne {}", ok_label);
+                self.gen_source_line_args(line, "%rdi", "%rsi");
+                self.gen_runtime_call("mjrt_null_pointer");
+                self.label(&ok_label);
+                return;
+            }
+            Instruction::CheckBounds { array, index, line } => {
+                let ok_label = self.new_label();
+                emit!(self, "movq {}, %rcx", frame.location(*array));
+                emit!(self, "movq {}, %rax", frame.location(*index));
+                // negative indices are greater than any length when they
+                // are compared as unsigned values
+                emit!(self, "cmpq (%rcx), %rax");
+                emit!(self, "jb {}", ok_label);
+                emit!(self, "movq %rax, %rdi");
+                emit!(self, "movq (%rcx), %rsi");
+                self.gen_source_line_args(line, "%rdx", "%rcx");
+                self.gen_runtime_call("mjrt_index_out_of_bounds");
+                self.label(&ok_label);
+                return;
+            }
+        }
+        emit!(self, "movq %rax, {}", frame.location(value));
+    }
+
+    fn gen_terminator(&mut self, frame: &Frame<'_>, block: BlockId) {
+        match &frame.method.block(block).terminator {
+            Terminator::Jump(target) => self.gen_edge(frame, block, *target),
+            Terminator::Branch {
+                cond,
+                then_block,
+                else_block,
+            } => {
+                // every edge needs its own copies for the `Phi`s
+                let else_label = self.new_label();
+                emit!(self, "cmpq $0, {}", frame.location(*cond));
+                emit!(self, "je {}", else_label);
+                self.gen_edge(frame, block, *then_block);
+                self.label(&else_label);
+                self.gen_edge(frame, block, *else_block);
+            }
+            Terminator::Return(value) => {
+                match value {
+                    Some(value) => emit!(self, "movq {}, %rax", frame.location(*value)),
+                    None => emit!(self, "xorl %eax, %eax"),
+                }
+                if frame.max_roots > 0 {
+                    emit!(self, "movq {}, %rcx", frame.record_word(0));
+                    emit!(self, "movq %rcx, {}(%rip)", SHADOW_STACK);
+                }
+                self.gen_epilogue(&frame.allocation.callee_saved);
+            }
+            Terminator::Unreachable => emit!(self, "ud2"),
+        }
+    }
+
+    /// Restore the callee-saved registers and return.
+    fn gen_epilogue(&mut self, callee_saved: &[Register]) {
+        if callee_saved.is_empty() {
+            emit!(self, "movq %rbp, %rsp");
+        } else {
+            let saved_size = callee_saved.len() as i64 * VALUE_SIZE;
+            emit!(self, "leaq {}(%rbp), %rsp", -saved_size);
+            for register in callee_saved.iter().rev() {
+                emit!(self, "popq {}", register);
+            }
+        }
+        emit!(self, "popq %rbp");
+        emit!(self, "ret");
+    }
+
+    /// Store the roots of an instruction that may collect garbage in the
+    /// frame record.
+    fn gen_roots(&mut self, frame: &Frame<'_>, value: ValueId) {
+        let roots = frame.roots(value);
+        emit!(self, "movq ${}, {}", roots.len(), frame.record_word(1));
+        for (index, root) in roots.into_iter().enumerate() {
+            emit!(self, "movq {}, %rax", frame.location(root));
+            emit!(self, "movq %rax, {}", frame.record_word(2 + index));
+        }
+    }
+
+    /// Copy the incoming values of the `Phi`s of `to` and jump to it. All
+    /// values are read before the first one is written, because a `Phi` may
+    /// be an operand of another `Phi` of the same block.
+    fn gen_edge(&mut self, frame: &Frame<'_>, from: BlockId, to: BlockId) {
+        let copies: Vec<_> = frame
+            .method
+            .block(to)
+            .instructions
+            .iter()
+            .filter_map(|&phi| match frame.method.value(phi) {
+                Instruction::Phi(operands) => operands
+                    .iter()
+                    .find(|(predecessor, _)| *predecessor == from)
+                    .map(|(_, value)| (phi, *value)),
+                _ => None,
+            })
+            .collect();
+
+        for (_, value) in &copies {
+            emit!(self, "pushq {}", frame.location(*value));
+        }
+        for (phi, _) in copies.iter().rev() {
+            emit!(self, "popq {}", frame.location(*phi));
+        }
+        emit!(self, "jmp {}", frame.block_label(to));
+    }
+
+    /// Combine `%rax` (lhs) and `%rcx` (rhs), the result is left in `%rax`.
+    fn gen_binary_op(&mut self, op: BinaryOp) {
+        use crate::ir::BinaryOp::*;
+        let (setcc, arith) = match op {
+            Equals => ("sete", ""),
+            NotEquals => ("setne", ""),
+            LessThan => ("setl", ""),
+            GreaterThan => ("setg", ""),
+            LessEquals => ("setle", ""),
+            GreaterEquals => ("setge", ""),
+            Add => ("", "addl %ecx, %eax"),
+            Sub => ("", "subl %ecx, %eax"),
+            Mul => ("", "imull %ecx, %eax"),
+            Div | Mod => {
+                self.gen_division(op);
+                return;
+            }
+        };
+
+        if setcc.is_empty() {
+            emit!(self, "{}", arith);
+            emit!(self, "cltq");
+        } else {
+            emit!(self, "cmpq %rcx, %rax");
+            emit!(self, "{} %al", setcc);
+            emit!(self, "movzbl %al, %eax");
+        }
+    }
+
+    /// `idiv` traps on `i32::MIN / -1`, which is `i32::MIN` in Java, so a
+    /// divisor of `-1` is handled separately.
+    fn gen_division(&mut self, op: BinaryOp) {
+        let idiv_label = self.new_label();
+        let end_label = self.new_label();
+        emit!(self, "cmpl $-1, %ecx");
+        emit!(self, "jne {}", idiv_label);
+        if op == BinaryOp::Div {
+            emit!(self, "negl %eax");
+        } else {
+            emit!(self, "xorl %eax, %eax");
+        }
+        emit!(self, "jmp {}", end_label);
+        self.label(&idiv_label);
+        emit!(self, "cltd");
+        emit!(self, "idivl %ecx");
+        if op == BinaryOp::Mod {
+            emit!(self, "movl %edx, %eax");
+        }
+        self.label(&end_label);
+        emit!(self, "cltq");
+    }
+
+    fn gen_builtin_call(&mut self, method: BuiltinMethod) {
+        let function = match method {
+            BuiltinMethod::Println => "mjrt_println",
+            BuiltinMethod::Write => "mjrt_write",
+            BuiltinMethod::Flush => "mjrt_flush",
+            BuiltinMethod::Read => "mjrt_read",
+        };
+        self.gen_runtime_call(function);
+        if method == BuiltinMethod::Read {
+            // the runtime returns a 32 bit int
+            emit!(self, "cltq");
+        }
+    }
+
+    /// Pass the number and the text of a source line to a runtime function.
+    fn gen_source_line_args(&mut self, line: &SourceLine, number: &str, text: &str) {
+        self.source_lines
+            .entry(line.number)
+            .or_insert_with(|| line.text.clone());
+        emit!(self, "movq ${}, {}", line.number, number);
+        emit!(
+            self,
+            "leaq {}(%rip), {}",
+            source_line_label(line.number),
+            text
+        );
+    }
+
+    /// Call a function of the runtime. Its arguments are passed in
+    /// registers, so the stack is already aligned.
+    fn gen_runtime_call(&mut self, function: &str) {
+        emit!(self, "call {}", function);
+    }
+
+    fn field_offset(&self, field: &ir::FieldRef) -> i64 {
+        self.program.classes[&field.class_name].field_index(&field.name) as i64 * VALUE_SIZE
+    }
+
+    fn new_label(&mut self) -> String {
+        self.next_label += 1;
+        format!(".L{}", self.next_label)
+    }
+
+    fn label(&mut self, label: &str) {
+        writeln!(self.out, "{}:", label).unwrap();
+    }
+}
+
+/// The address of the element at the index in `index_register` of the array
+/// in `%rcx`. The elements follow the length of the array.
+fn element_address(index_register: &str) -> String {
+    format!("{}(%rcx,{},{})", VALUE_SIZE, index_register, VALUE_SIZE)
+}
+
+/// Whether the garbage collector may run during the instruction. The
+/// built-in methods never allocate.
+fn may_collect(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::Call { .. } | Instruction::NewObject(_) | Instruction::NewArray(..) => true,
+        _ => false,
+    }
+}
+
+fn reference_map_label(class_name: &str) -> String {
+    format!(".Lrefs_{}", class_name)
+}
+
+fn source_line_label(number: usize) -> String {
+    format!(".Lline{}", number)
+}
+
+/// Escape a string for an `.asciz` directive. The source files are ASCII,
+/// so only quotes, backslashes and control characters need escaping.
+fn escape_string(string: &str) -> String {
+    let mut escaped = String::new();
+    for c in string.chars() {
+        match c {
+            '"' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c if c.is_ascii_control() => write!(escaped, "\\{:03o}", c as u8).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The assembly label of a method. The length prefix keeps the labels of
+/// e.g. `A_b.c` and `A.b_c` apart.
+fn mangle(class_name: &str, method_name: &str) -> String {
+    format!("mj_{}{}_{}", class_name.len(), class_name, method_name)
+}
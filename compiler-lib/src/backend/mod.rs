@@ -0,0 +1,73 @@
+//! The x86-64 backend, which compiles the IR of a program to a native
+//! executable.
+//!
+//! `codegen` translates the IR to assembly for the System V ABI, keeping
+//! values in the registers chosen by `register_allocation`. The assembly is
+//! then assembled and linked by the system's C compiler `cc`, together with
+//! the small runtime in `runtime.c`, which implements the built-in methods
+//! and memory allocation. The runtime is embedded into the
+//! compiler, so no files besides the compiler binary need to be installed.
+mod codegen;
+mod register_allocation;
+
+use crate::ir;
+use failure::{Error, Fail, ResultExt};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+
+const RUNTIME_SOURCE: &str = include_str!("runtime.c");
+
+/// The C compiler used to assemble and link the generated code
+const CC: &str = "cc";
+
+#[derive(Debug, Fail)]
+pub enum BackendError {
+    #[fail(display = "cannot write temporary file {:?}", path)]
+    WriteTemporaryFile { path: PathBuf },
+    #[fail(display = "cannot run the C compiler '{}'", cc)]
+    RunCc { cc: String },
+    #[fail(display = "assembling and linking the program failed: {}", output)]
+    Link { output: String },
+}
+
+/// Compile `program` to a native executable at `output`.
+pub fn compile(program: &ir::Program, output: &Path) -> Result<(), Error> {
+    let dir = env::temp_dir().join(format!("comprakt-{}", process::id()));
+    let result = fs::create_dir_all(&dir)
+        .context(BackendError::WriteTemporaryFile { path: dir.clone() })
+        .map_err(Error::from)
+        .and_then(|()| link(&codegen::generate(program), &dir, output));
+
+    // the temporary files are useless if linking failed, too
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+fn link(asm: &str, dir: &Path, output: &Path) -> Result<(), Error> {
+    let asm_path = dir.join("program.s");
+    let runtime_path = dir.join("runtime.c");
+    for (path, contents) in &[(&asm_path, asm), (&runtime_path, RUNTIME_SOURCE)] {
+        fs::write(path, contents).context(BackendError::WriteTemporaryFile {
+            path: path.to_path_buf(),
+        })?;
+    }
+
+    let result = Command::new(CC)
+        .arg("-o")
+        .arg(output)
+        .arg(&asm_path)
+        .arg(&runtime_path)
+        .output()
+        .context(BackendError::RunCc { cc: CC.to_string() })?;
+
+    if !result.status.success() {
+        Err(BackendError::Link {
+            output: String::from_utf8_lossy(&result.stderr).into_owned(),
+        })?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,522 @@
+//! Linear scan register allocation, after Poletto and Sarkar.
+//!
+//! The blocks of a method are numbered in the order in which they are
+//! emitted, i.e. in reverse postorder. Every value gets a single live
+//! interval, which spans all positions at which the value is live according
+//! to a liveness analysis on the control flow graph. A `Phi` is written at
+//! the end of each predecessor, so its interval covers these positions as
+//! well.
+//!
+//! The intervals are processed by increasing start position. Each gets a
+//! free register if there is one. Otherwise either the interval itself or
+//! the active interval that ends last is spilled to a stack slot for its
+//! whole lifetime.
+//!
+//! Every call clobbers the caller-saved registers, so values that are live
+//! across a call only get callee-saved registers. Methods save the
+//! callee-saved registers they use in their prologue. The allocation also
+//! records which values are live across each call, which the code generator
+//! hands to the garbage collector as roots.
+//!
+//! `%rax`, `%rcx` and `%rdx` are never allocated. The code generator uses
+//! them as scratch registers to load operands from stack slots and to
+//! compute results.
+use crate::ir::{BlockId, Instruction, Method, ValueId};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Register {
+    Rbx,
+    R12,
+    R13,
+    R14,
+    R15,
+    Rsi,
+    Rdi,
+    R8,
+    R9,
+    R10,
+    R11,
+}
+
+/// Preserved across calls, must be saved by the callee if it uses them
+const CALLEE_SAVED: [Register; 5] = [
+    Register::Rbx,
+    Register::R12,
+    Register::R13,
+    Register::R14,
+    Register::R15,
+];
+
+/// Clobbered by calls
+const CALLER_SAVED: [Register; 6] = [
+    Register::Rsi,
+    Register::Rdi,
+    Register::R8,
+    Register::R9,
+    Register::R10,
+    Register::R11,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Register(Register),
+    /// The index of a stack slot in the frame of the method
+    Stack(usize),
+}
+
+#[derive(Debug)]
+pub struct Allocation {
+    /// The location of every value, indexed by `ValueId`. Values that are
+    /// not part of any block, or do not produce a result, have none.
+    pub locations: Vec<Option<Location>>,
+    pub stack_slots: usize,
+    /// The callee-saved registers used by the method, which its prologue
+    /// has to save
+    pub callee_saved: Vec<Register>,
+    /// The values that are live across each call, i.e. that are defined
+    /// before and used after it, ordered by `ValueId`
+    pub live_across_calls: HashMap<ValueId, Vec<ValueId>>,
+}
+
+impl Register {
+    pub fn is_callee_saved(self) -> bool {
+        CALLEE_SAVED.contains(&self)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Interval {
+    value: ValueId,
+    start: usize,
+    end: usize,
+}
+
+pub fn allocate(method: &Method) -> Allocation {
+    let numbering = Numbering::new(method);
+    let live_in = live_in(method, &numbering.order);
+    let intervals = live_intervals(method, &numbering, &live_in);
+    let mut allocation = LinearScan::new(method, &numbering).run(intervals);
+    allocation.live_across_calls = live_across_calls(method, &numbering.order, &live_in);
+    allocation
+}
+
+/// The positions of the blocks and instructions in the emitted order.
+struct Numbering {
+    order: Vec<BlockId>,
+    /// The position of the start of each block, indexed by `BlockId`
+    block_start: Vec<usize>,
+    /// The position of the terminator of each block, indexed by `BlockId`
+    block_end: Vec<usize>,
+    /// The position of each value, indexed by `ValueId`
+    value_position: Vec<usize>,
+    /// The positions of all instructions that call a function
+    calls: Vec<usize>,
+}
+
+impl Numbering {
+    fn new(method: &Method) -> Self {
+        let order = method.reverse_postorder();
+        let mut numbering = Numbering {
+            block_start: vec![0; method.blocks.len()],
+            block_end: vec![0; method.blocks.len()],
+            value_position: vec![0; method.values.len()],
+            calls: Vec::new(),
+            order,
+        };
+
+        let mut position = 0;
+        for &block in &numbering.order {
+            numbering.block_start[block.0] = position;
+            for &value in &method.block(block).instructions {
+                position += 1;
+                numbering.value_position[value.0] = position;
+                if is_call(method.value(value)) {
+                    numbering.calls.push(position);
+                }
+            }
+            position += 1;
+            numbering.block_end[block.0] = position;
+            position += 1;
+        }
+        numbering
+    }
+}
+
+fn is_call(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::Call { .. }
+        | Instruction::CallBuiltin(..)
+        | Instruction::NewObject(_)
+        | Instruction::NewArray(..) => true,
+        _ => false,
+    }
+}
+
+/// Whether the instruction defines a value that can be used.
+fn has_result(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::StoreField { .. }
+        | Instruction::StoreElement { .. }
+        | Instruction::CheckNull { .. }
+        | Instruction::CheckBounds { .. } => false,
+        _ => true,
+    }
+}
+
+/// The values that are live at the start of every block, not counting the
+/// `Phi`s of the block, indexed by `BlockId`.
+fn live_in(method: &Method, order: &[BlockId]) -> Vec<HashSet<ValueId>> {
+    let mut live_in = vec![HashSet::new(); method.blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in order.iter().rev() {
+            let mut live = live_out(method, &live_in, block);
+            if let Some(operand) = method.block(block).terminator.operand() {
+                live.insert(operand);
+            }
+            for &value in method.block(block).instructions.iter().rev() {
+                live.remove(&value);
+                if !method.value(value).is_phi() {
+                    live.extend(method.value(value).operands());
+                }
+            }
+            if live != live_in[block.0] {
+                live_in[block.0] = live;
+                changed = true;
+            }
+        }
+    }
+    live_in
+}
+
+/// The values that are live at the end of `block`, including the operands
+/// of the `Phi`s of its successors that flow in from `block`.
+fn live_out(method: &Method, live_in: &[HashSet<ValueId>], block: BlockId) -> HashSet<ValueId> {
+    let mut live = HashSet::new();
+    for successor in method.block(block).terminator.successors() {
+        live.extend(&live_in[successor.0]);
+        for &value in &method.block(successor).instructions {
+            if let Instruction::Phi(operands) = method.value(value) {
+                live.extend(
+                    operands
+                        .iter()
+                        .filter(|(predecessor, _)| *predecessor == block)
+                        .map(|(_, operand)| *operand),
+                );
+            }
+        }
+    }
+    live
+}
+
+/// The values that are live after each call, not counting its result.
+/// Unlike the intervals, this has no holes: every value in it is defined on
+/// all paths to the call, so its location holds it.
+fn live_across_calls(
+    method: &Method,
+    order: &[BlockId],
+    live_in: &[HashSet<ValueId>],
+) -> HashMap<ValueId, Vec<ValueId>> {
+    let mut live_across_calls = HashMap::new();
+    for &block in order {
+        let mut live = live_out(method, live_in, block);
+        if let Some(operand) = method.block(block).terminator.operand() {
+            live.insert(operand);
+        }
+        for &value in method.block(block).instructions.iter().rev() {
+            live.remove(&value);
+            if is_call(method.value(value)) {
+                let mut values: Vec<_> = live.iter().cloned().collect();
+                values.sort();
+                live_across_calls.insert(value, values);
+            }
+            if !method.value(value).is_phi() {
+                live.extend(method.value(value).operands());
+            }
+        }
+    }
+    live_across_calls
+}
+
+/// The live intervals of all values with a result, ordered by start
+/// position.
+fn live_intervals(
+    method: &Method,
+    numbering: &Numbering,
+    live_in: &[HashSet<ValueId>],
+) -> Vec<Interval> {
+    let mut ranges: Vec<Option<(usize, usize)>> = vec![None; method.values.len()];
+    let mut extend = |value: ValueId, position: usize| {
+        let range = ranges[value.0].get_or_insert((position, position));
+        range.0 = range.0.min(position);
+        range.1 = range.1.max(position);
+    };
+
+    for &block in &numbering.order {
+        let start = numbering.block_start[block.0];
+        let end = numbering.block_end[block.0];
+        for &value in &live_in[block.0] {
+            extend(value, start);
+        }
+        for value in live_out(method, &live_in, block) {
+            extend(value, end);
+        }
+        for &value in &method.block(block).instructions {
+            let position = numbering.value_position[value.0];
+            extend(value, position);
+            match method.value(value) {
+                // the incoming values are copied at the end of the
+                // predecessors
+                Instruction::Phi(operands) => {
+                    for (predecessor, _) in operands {
+                        extend(value, numbering.block_end[predecessor.0]);
+                    }
+                }
+                instruction => {
+                    for operand in instruction.operands() {
+                        extend(operand, position);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut intervals: Vec<_> = ranges
+        .into_iter()
+        .enumerate()
+        .filter(|&(value, _)| has_result(method.value(ValueId(value))))
+        .filter_map(|(value, range)| {
+            range.map(|(start, end)| Interval {
+                value: ValueId(value),
+                start,
+                end,
+            })
+        })
+        .collect();
+    intervals.sort_by_key(|interval| (interval.start, interval.value));
+    intervals
+}
+
+struct LinearScan<'m> {
+    numbering: &'m Numbering,
+    allocation: Allocation,
+    /// The intervals that currently occupy a register
+    active: Vec<(Interval, Register)>,
+    free: Vec<Register>,
+}
+
+impl<'m> LinearScan<'m> {
+    fn new(method: &Method, numbering: &'m Numbering) -> Self {
+        // registers at the end are taken first, so caller-saved registers
+        // are preferred, which do not have to be saved in the prologue
+        let mut free = CALLEE_SAVED.to_vec();
+        free.extend(CALLER_SAVED.iter().rev());
+        LinearScan {
+            numbering,
+            allocation: Allocation {
+                locations: vec![None; method.values.len()],
+                stack_slots: 0,
+                callee_saved: Vec::new(),
+                live_across_calls: HashMap::new(),
+            },
+            active: Vec::new(),
+            free,
+        }
+    }
+
+    fn run(mut self, intervals: Vec<Interval>) -> Allocation {
+        for interval in intervals {
+            self.expire(interval.start);
+
+            let crosses_call = self.crosses_call(interval);
+            let usable = |register: Register| !crosses_call || register.is_callee_saved();
+            match self.free.iter().rposition(|&register| usable(register)) {
+                Some(index) => {
+                    let register = self.free.remove(index);
+                    self.assign(interval, register);
+                }
+                None => {
+                    // spill the interval that ends last
+                    let candidate = self
+                        .active
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, (_, register))| usable(*register))
+                        .max_by_key(|(_, (active, _))| active.end)
+                        .map(|(index, _)| index);
+                    match candidate {
+                        Some(index) if self.active[index].0.end > interval.end => {
+                            let (spilled, register) = self.active.remove(index);
+                            self.spill(spilled);
+                            self.assign(interval, register);
+                        }
+                        _ => self.spill(interval),
+                    }
+                }
+            }
+        }
+        self.allocation
+    }
+
+    /// Free the registers of the intervals that end before `position`.
+    fn expire(&mut self, position: usize) {
+        let free = &mut self.free;
+        self.active.retain(|(interval, register)| {
+            let expired = interval.end < position;
+            if expired {
+                free.push(*register);
+            }
+            !expired
+        });
+    }
+
+    /// Whether a call clobbers the caller-saved registers while the value
+    /// is live. The operands of a call are read before, and its result is
+    /// written after the registers are clobbered.
+    fn crosses_call(&self, interval: Interval) -> bool {
+        self.numbering
+            .calls
+            .iter()
+            .any(|&call| interval.start < call && call < interval.end)
+    }
+
+    fn assign(&mut self, interval: Interval, register: Register) {
+        self.allocation.locations[interval.value.0] = Some(Location::Register(register));
+        if register.is_callee_saved() && !self.allocation.callee_saved.contains(&register) {
+            self.allocation.callee_saved.push(register);
+        }
+        self.active.push((interval, register));
+    }
+
+    fn spill(&mut self, interval: Interval) {
+        let slot = self.allocation.stack_slots;
+        self.allocation.stack_slots += 1;
+        self.allocation.locations[interval.value.0] = Some(Location::Stack(slot));
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::Register::*;
+        write!(
+            f,
+            "%{}",
+            match self {
+                Rbx => "rbx",
+                R12 => "r12",
+                R13 => "r13",
+                R14 => "r14",
+                R15 => "r15",
+                Rsi => "rsi",
+                Rdi => "rdi",
+                R8 => "r8",
+                R9 => "r9",
+                R10 => "r10",
+                R11 => "r11",
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{self, Program};
+
+    /// Allocate registers for the method `f`, next to a method `g`.
+    fn allocate_method(method: &str) -> (Program, Allocation) {
+        let program = ir::lower_method(&format!("public int g() {{ return 0; }} {}", method));
+        let allocation = allocate(program.method("f"));
+        (program, allocation)
+    }
+
+    /// Check that values whose intervals overlap never share a register.
+    fn assert_no_conflicts(method: &Method, allocation: &Allocation) {
+        let numbering = Numbering::new(method);
+        let live_in = live_in(method, &numbering.order);
+        let intervals = live_intervals(method, &numbering, &live_in);
+        for a in &intervals {
+            for b in &intervals {
+                let overlap = a.value != b.value && a.start <= b.end && b.start <= a.end;
+                let location = allocation.locations[a.value.0];
+                if overlap {
+                    if let Some(Location::Register(_)) = location {
+                        assert_ne!(location, allocation.locations[b.value.0]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn few_values_are_not_spilled() {
+        let (program, allocation) = allocate_method(
+            "public int f(int x, int y) { int z = x + y; while (z < 10) z = z * x; return z; }",
+        );
+        assert_eq!(allocation.stack_slots, 0);
+        assert!(allocation.callee_saved.is_empty());
+        assert_no_conflicts(program.method("f"), &allocation);
+    }
+
+    #[test]
+    fn values_live_across_calls_are_in_callee_saved_registers() {
+        let (program, allocation) =
+            allocate_method("public int f(int x) { int y = x * 2; g(); return x + y; }");
+        let method = program.method("f");
+        assert_no_conflicts(method, &allocation);
+        for (value, instruction) in method.values.iter().enumerate() {
+            // `x` and `y`, but not the receiver, which is only used by the call
+            let live_across_call = match instruction {
+                Instruction::Param(index) => *index == 1,
+                Instruction::Binary(op, ..) => *op == ir::BinaryOp::Mul,
+                _ => false,
+            };
+            if live_across_call {
+                match allocation.locations[value] {
+                    Some(Location::Register(register)) => assert!(register.is_callee_saved()),
+                    Some(Location::Stack(_)) => (),
+                    None => panic!("value {} has no location", value),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn values_used_after_a_call_are_live_across_it() {
+        let (program, allocation) =
+            allocate_method("public int f(int x) { int y = x * 2; g(); return x + y; }");
+        let method = program.method("f");
+        let mut call = None;
+        let mut expected = Vec::new();
+        for (value, instruction) in method.values.iter().enumerate() {
+            match instruction {
+                Instruction::Call { .. } => call = Some(ValueId(value)),
+                Instruction::Param(1) | Instruction::Binary(ir::BinaryOp::Mul, ..) => {
+                    expected.push(ValueId(value))
+                }
+                _ => (),
+            }
+        }
+        assert_eq!(allocation.live_across_calls[&call.unwrap()], expected);
+    }
+
+    #[test]
+    fn values_are_spilled_when_registers_run_out() {
+        let sums: Vec<_> = (0..16)
+            .map(|i| format!("int a{} = x + {};", i, i))
+            .collect();
+        let uses: Vec<_> = (0..16).map(|i| format!("a{}", i)).collect();
+        let (program, allocation) = allocate_method(&format!(
+            "public int f(int x) {{ {} return {}; }}",
+            sums.join(" "),
+            uses.join(" + ")
+        ));
+        assert!(allocation.stack_slots > 0);
+        assert_no_conflicts(program.method("f"), &allocation);
+    }
+}
@@ -0,0 +1,243 @@
+/*
+ * The runtime library linked into every compiled MiniJava program.
+ *
+ * The generated code calls these functions using the System V calling
+ * convention. `minijava_main` is provided by the generated assembly.
+ */
+#include <inttypes.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+/* The exit statuses of failed run-time checks */
+#define EXIT_NULL_POINTER 2
+#define EXIT_INDEX_OUT_OF_BOUNDS 3
+
+void minijava_main(void);
+
+static void mjrt_fail(const char *message) {
+    fflush(stdout);
+    fprintf(stderr, "error: %s\n", message);
+    exit(1);
+}
+
+/* Report a failed check of the code in the given source line. */
+static void mjrt_fail_at(const char *message, int64_t line, const char *text, int status) {
+    fflush(stdout);
+    fprintf(stderr, "error: %s\n", message);
+    fprintf(stderr, " %" PRId64 " | %s\n", line, text);
+    exit(status);
+}
+
+void mjrt_null_pointer(int64_t line, const char *text) {
+    mjrt_fail_at("null pointer dereference", line, text, EXIT_NULL_POINTER);
+}
+
+void mjrt_index_out_of_bounds(int64_t index, int64_t length, int64_t line, const char *text) {
+    char message[100];
+    snprintf(message, sizeof(message),
+             "array index %" PRId64 " is out of bounds for length %" PRId64, index, length);
+    mjrt_fail_at(message, line, text, EXIT_INDEX_OUT_OF_BOUNDS);
+}
+
+void mjrt_println(int32_t value) {
+    printf("%d\n", value);
+}
+
+void mjrt_write(int32_t value) {
+    /* like Java, only the low-order eight bits are written */
+    putchar((unsigned char)value);
+}
+
+void mjrt_flush(void) {
+    fflush(stdout);
+}
+
+int32_t mjrt_read(void) {
+    int c = getchar();
+    return c == EOF ? -1 : c;
+}
+
+/*
+ * Garbage collection
+ *
+ * Objects and arrays are allocated with `calloc`, preceded by a header that
+ * links all allocations into a list. Once the memory allocated since the
+ * last collection exceeds a threshold, a mark-and-sweep collection frees the
+ * allocations that are no longer reachable.
+ *
+ * The roots are found on the shadow stack: every MiniJava method that holds
+ * references across a call or an allocation links a frame record into the
+ * list starting at `mjrt_shadow_stack`, and stores these references in it
+ * before each such instruction. Values that are not in a record are either
+ * not references or not used after the collection.
+ *
+ * The header records the kind of every allocation. Objects point to the map
+ * of their class, which lists the indices of the fields that hold
+ * references. The elements of arrays of references are scanned, while
+ * arrays of ints and booleans are not scanned at all.
+ */
+
+enum kind {
+    KIND_OBJECT,
+    KIND_REFERENCE_ARRAY,
+    KIND_VALUE_ARRAY,
+};
+
+struct header {
+    struct header *next;
+    /* the size of the allocation without the header in bytes */
+    int64_t size;
+    /*
+     * for objects, the number of fields that hold references followed by
+     * their indices
+     */
+    const int64_t *references;
+    int32_t kind;
+    int32_t marked;
+};
+
+/* The record of the references of a MiniJava method that survive a call */
+struct frame {
+    struct frame *prev;
+    int64_t count;
+    void *roots[];
+};
+
+/* The innermost frame record, maintained by the generated code */
+struct frame *mjrt_shadow_stack;
+
+#define MIN_COLLECTION_THRESHOLD (8 << 20)
+
+static struct header *heap;
+static int64_t allocated_since_collection;
+static int64_t collection_threshold = MIN_COLLECTION_THRESHOLD;
+
+/* The allocations that are marked, but whose contents are not scanned yet */
+static struct header **mark_stack;
+static size_t mark_stack_capacity;
+static size_t mark_stack_size;
+
+static void *payload(struct header *header) {
+    return header + 1;
+}
+
+static void mark(void *reference) {
+    if (reference == NULL) {
+        return;
+    }
+    struct header *header = (struct header *)reference - 1;
+    if (header->marked) {
+        return;
+    }
+    header->marked = 1;
+    if (mark_stack_size == mark_stack_capacity) {
+        mark_stack_capacity = mark_stack_capacity > 0 ? 2 * mark_stack_capacity : 1024;
+        mark_stack = realloc(mark_stack, mark_stack_capacity * sizeof(*mark_stack));
+        if (mark_stack == NULL) {
+            mjrt_fail("out of memory");
+        }
+    }
+    mark_stack[mark_stack_size++] = header;
+}
+
+/* Mark everything reachable from the frame records. */
+static void mark_from_roots(void) {
+    for (struct frame *frame = mjrt_shadow_stack; frame != NULL; frame = frame->prev) {
+        for (int64_t i = 0; i < frame->count; i++) {
+            mark(frame->roots[i]);
+        }
+    }
+    while (mark_stack_size > 0) {
+        struct header *header = mark_stack[--mark_stack_size];
+        void **contents = payload(header);
+        int64_t length = header->size / sizeof(void *);
+        switch (header->kind) {
+        case KIND_OBJECT:
+            for (int64_t i = 1; i <= header->references[0]; i++) {
+                mark(contents[header->references[i]]);
+            }
+            break;
+        case KIND_REFERENCE_ARRAY:
+            /* skip the length */
+            for (int64_t i = 1; i < length; i++) {
+                mark(contents[i]);
+            }
+            break;
+        case KIND_VALUE_ARRAY:
+            break;
+        }
+    }
+}
+
+static void collect(void) {
+    mark_from_roots();
+
+    int64_t live = 0;
+    struct header **link = &heap;
+    while (*link != NULL) {
+        struct header *header = *link;
+        if (header->marked) {
+            header->marked = 0;
+            live += header->size;
+            link = &header->next;
+        } else {
+            *link = header->next;
+            free(header);
+        }
+    }
+
+    allocated_since_collection = 0;
+    collection_threshold = live > MIN_COLLECTION_THRESHOLD ? live : MIN_COLLECTION_THRESHOLD;
+}
+
+/* Allocate `size` zero-initialized bytes that are freed by the collector. */
+static void *allocate(int64_t size, enum kind kind, const int64_t *references) {
+    if (allocated_since_collection >= collection_threshold) {
+        collect();
+    }
+    struct header *header = calloc(1, sizeof(struct header) + size);
+    if (header == NULL) {
+        collect();
+        header = calloc(1, sizeof(struct header) + size);
+        if (header == NULL) {
+            mjrt_fail("out of memory");
+        }
+    }
+    header->size = size;
+    header->references = references;
+    header->kind = kind;
+    header->next = heap;
+    heap = header;
+    allocated_since_collection += size;
+    return payload(header);
+}
+
+/*
+ * Allocate a zero-initialized object of `size` bytes. `references` is the
+ * map of the fields of its class that hold references.
+ */
+void *mjrt_new(int64_t size, const int64_t *references) {
+    return allocate(size, KIND_OBJECT, references);
+}
+
+/*
+ * Allocate a zero-initialized array of `length` 8 byte elements. The first
+ * 8 bytes hold the length, the elements follow. The collector only scans
+ * the elements if `references` is set.
+ */
+void *mjrt_new_array(int64_t length, int64_t references) {
+    if (length < 0) {
+        mjrt_fail("cannot create an array of negative size");
+    }
+    enum kind kind = references ? KIND_REFERENCE_ARRAY : KIND_VALUE_ARRAY;
+    int64_t *array = allocate((length + 1) * 8, kind, NULL);
+    array[0] = length;
+    return array;
+}
+
+int main(void) {
+    minijava_main();
+    fflush(stdout);
+    return 0;
+}
@@ -5,11 +5,19 @@
 //! This implementation is NOT thread-safe. Messages from different threads may
 //! be interleaved.
 use crate::{
-    asciifile::{MaybeSpanned, Span, Spanned},
+    asciifile::{MaybeSpanned, Position, Span, Spanned},
     color::ColorOutput,
+    error_codes::{Coded, ErrorCode},
+    lints::{Lint, LintLevel, LintSelector, Linted},
 };
 use failure::Error;
-use std::{ascii::escape_default, cell::RefCell, collections::HashMap, fmt::Display};
+use std::{
+    ascii::escape_default,
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
+    str::FromStr,
+};
 use termcolor::{Color, WriteColor};
 
 pub fn u8_to_printable_representation(byte: u8) -> String {
@@ -85,8 +93,6 @@ const TAB_WIDTH: usize = 4;
 /// and others...
 const HIGHLIGHT_COLOR: Option<Color> = Some(Color::Cyan);
 
-// TODO reimplement line truncation
-
 /// Instead of writing errors, warnings and lints generated in the different
 /// compiler stages directly to stdout, they are collected in this object.
 ///
@@ -96,6 +102,62 @@ const HIGHLIGHT_COLOR: Option<Color> = Some(Color::Cyan);
 pub struct Diagnostics {
     message_count: RefCell<HashMap<MessageLevel, usize>>,
     writer: RefCell<Box<dyn WriteColor>>,
+    format: ErrorFormat,
+    snippets: SnippetOptions,
+    /// Levels set for single lints, see `set_lint_level`
+    lint_levels: HashMap<&'static str, LintLevel>,
+    /// The level set for all lints
+    warnings_level: Option<LintLevel>,
+    /// The number of errors that are denied warnings
+    denied_warnings: Cell<usize>,
+    /// The lints whose level was already explained in a note
+    noted_lints: RefCell<HashSet<&'static str>>,
+}
+
+/// How messages are written.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// Colored text with source code snippets, intended for humans
+    Human,
+    /// One JSON object per line, intended for editors and scripts
+    Json,
+}
+
+impl FromStr for ErrorFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(ErrorFormat::Human),
+            "json" => Ok(ErrorFormat::Json),
+            _ => Err(format!(
+                "unknown error format '{}', expected human or json",
+                s
+            )),
+        }
+    }
+}
+
+/// How much source code is written below a message.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SnippetOptions {
+    /// The number of lines written above and below the underlined lines
+    pub context_lines: usize,
+    /// Spans covering more lines are shortened to their first and last
+    /// `max_span_lines / 2` lines
+    pub max_span_lines: usize,
+    /// Longer lines are truncated around their underlined parts
+    pub max_line_width: usize,
+}
+
+impl Default for SnippetOptions {
+    fn default() -> Self {
+        Self {
+            context_lines: 0,
+            max_span_lines: 4,
+            max_line_width: 120,
+        }
+    }
 }
 
 impl Diagnostics {
@@ -103,11 +165,45 @@ impl Diagnostics {
         Self {
             writer: RefCell::new(writer),
             message_count: RefCell::new(HashMap::new()),
+            format: ErrorFormat::Human,
+            snippets: SnippetOptions::default(),
+            lint_levels: HashMap::new(),
+            warnings_level: None,
+            denied_warnings: Cell::new(0),
+            noted_lints: RefCell::new(HashSet::new()),
         }
     }
 
+    pub fn set_format(&mut self, format: ErrorFormat) {
+        self.format = format;
+    }
+
+    pub fn set_snippet_options(&mut self, snippets: SnippetOptions) {
+        self.snippets = snippets;
+    }
+
+    /// Set the level of the selected lints. A level set for a single lint
+    /// takes precedence over the level set for all lints.
+    pub fn set_lint_level(&mut self, lints: LintSelector, level: LintLevel) {
+        match lints {
+            LintSelector::All => self.warnings_level = Some(level),
+            LintSelector::Lint(lint) => {
+                self.lint_levels.insert(lint.name, level);
+            }
+        }
+    }
+
+    pub fn lint_level(&self, lint: &Lint) -> LintLevel {
+        self.lint_levels
+            .get(lint.name)
+            .cloned()
+            .or(self.warnings_level)
+            .unwrap_or(lint.default_level)
+    }
+
     /// True when an error message was emitted, false
-    /// if only warnings were emitted.
+    /// if only warnings were emitted. Denied warnings
+    /// are errors.
     pub fn errored(&self) -> bool {
         self.message_count
             .borrow()
@@ -125,19 +221,36 @@ impl Diagnostics {
 
     pub fn write_statistics(&self) {
         let mut writer = self.writer.borrow_mut();
+        if self.format == ErrorFormat::Json {
+            // `ok()` surpresses io error
+            self.write_json_statistics(&mut **writer).ok();
+            return;
+        }
+
         let mut output = ColorOutput::new(&mut **writer);
 
         output.set_bold(true);
 
         if self.errored() {
+            let denied_warnings = self.denied_warnings.get();
+            let errors = self.count(MessageLevel::Error) - denied_warnings;
+            let mut reasons = Vec::new();
+            match errors {
+                0 => (),
+                1 => reasons.push("an error".to_string()),
+                n => reasons.push(format!("{} errors", n)),
+            }
+            match denied_warnings {
+                0 => (),
+                1 => reasons.push("a denied warning".to_string()),
+                n => reasons.push(format!("{} denied warnings", n)),
+            }
+
             output.set_color(MessageLevel::Error.color());
             writeln!(
                 output.writer(),
                 "Compilation aborted due to {}",
-                match self.count(MessageLevel::Error) {
-                    1 => "an error".to_string(),
-                    n => format!("{} errors", n),
-                }
+                reasons.join(" and ")
             );
         } else {
             output.set_color(Some(Color::Green));
@@ -153,16 +266,45 @@ impl Diagnostics {
         }
     }
 
+    /// The summary of all messages as a JSON object, e.g.
+    /// `{"type":"summary","errors":1,"denied_warnings":0,"warnings":0,"infos":
+    /// 0}`. Denied warnings are included in the number of errors.
+    fn write_json_statistics(&self, writer: &mut dyn WriteColor) -> Result<(), Error> {
+        writeln!(
+            writer,
+            r#"{{"type":"summary","errors":{},"denied_warnings":{},"warnings":{},"infos":{}}}"#,
+            self.count(MessageLevel::Error),
+            self.denied_warnings.get(),
+            self.count(MessageLevel::Warning),
+            self.count(MessageLevel::Info),
+        )?;
+        Ok(())
+    }
+
     /// Generate an error or a warning that is printed to the
     /// writer given in the `new` constructor. Most of the time
     /// this will be stderr.
     pub fn emit(&self, level: MessageLevel, kind: MaybeSpanned<'_, &dyn Display>) {
-        self.increment_level_count(level);
+        self.emit_message(&Message {
+            level,
+            kind,
+            code: None,
+            labels: Vec::new(),
+            footers: Vec::new(),
+        });
+    }
+
+    /// Like `emit`, but the message may have labels and footers, see
+    /// `Message::with_label`.
+    pub fn emit_message(&self, msg: &Message<'_, '_>) {
+        self.increment_level_count(msg.level);
         let mut writer = self.writer.borrow_mut();
-        let msg = Message { level, kind };
 
         // `ok()` surpresses io error
-        msg.write(&mut **writer).ok();
+        match self.format {
+            ErrorFormat::Human => msg.write(&mut **writer, &self.snippets).ok(),
+            ErrorFormat::Json => msg.write_json(&mut **writer).ok(),
+        };
     }
 
     #[allow(dead_code)]
@@ -175,6 +317,46 @@ impl Diagnostics {
         self.emit(MessageLevel::Error, kind.as_maybe_spanned())
     }
 
+    /// Like `error`, but the code of the error is written in the header of
+    /// the message, see `error_codes`.
+    pub fn coded_error<'a, 'b, T>(&self, error: &'b T)
+    where
+        T: Printable<'a, 'b> + Coded + ?Sized,
+    {
+        self.emit_message(&Message::new(MessageLevel::Error, error).with_code(error.code()))
+    }
+
+    /// Report a warning at the level of its lint: allowed warnings are
+    /// dropped, denied warnings are reported as errors. The first message
+    /// of every lint notes how to change its level.
+    pub fn lint<'a, 'b, T>(&self, warning: &'b T)
+    where
+        T: Printable<'a, 'b> + Linted + ?Sized,
+    {
+        let lint = warning.lint();
+        let level = self.lint_level(lint);
+        let (message_level, note) = match level {
+            LintLevel::Allow => return,
+            LintLevel::Warn => (
+                MessageLevel::Warning,
+                format!("`-A {}` disables this warning", lint.name),
+            ),
+            LintLevel::Deny => {
+                self.denied_warnings.set(self.denied_warnings.get() + 1);
+                (
+                    MessageLevel::Error,
+                    format!("the lint `{}` is denied", lint.name),
+                )
+            }
+        };
+
+        let mut message = Message::new(message_level, warning);
+        if self.noted_lints.borrow_mut().insert(lint.name) {
+            message = message.with_note(note);
+        }
+        self.emit_message(&message);
+    }
+
     #[allow(dead_code)]
     pub fn info<'a, 'b, T: Printable<'a, 'b> + ?Sized>(&self, kind: &'b T) {
         self.emit(MessageLevel::Info, kind.as_maybe_spanned())
@@ -218,30 +400,182 @@ impl MessageLevel {
 pub struct Message<'file, 'msg> {
     pub level: MessageLevel,
     pub kind: MaybeSpanned<'file, &'msg dyn Display>,
+    /// The code of the error, e.g. `E0101`
+    pub code: Option<ErrorCode>,
+    /// Secondary spans that explain the message, e.g. the first
+    /// declaration of a name that is redeclared at the primary span
+    pub labels: Vec<Spanned<'file, String>>,
+    /// `note:` and `help:` lines printed below the source code
+    pub footers: Vec<Footer>,
+}
+
+/// A line printed below the source code of a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Footer {
+    /// Additional context, e.g. why something is not allowed
+    Note(String),
+    /// A suggestion how to fix the problem
+    Help(String),
+}
+
+impl Footer {
+    fn name(&self) -> &str {
+        match self {
+            Footer::Note(_) => "note",
+            Footer::Help(_) => "help",
+        }
+    }
+
+    fn text(&self) -> &str {
+        match self {
+            Footer::Note(text) | Footer::Help(text) => text,
+        }
+    }
+}
+
+/// A span underlined in the source code of a message.
+struct Annotation<'a, 'file> {
+    span: &'a Span<'file>,
+    /// The primary span is underlined with `^`, labels with `-`
+    is_primary: bool,
+    text: Option<&'a str>,
 }
 
 impl<'file, 'msg> Message<'file, 'msg> {
-    pub fn write(&self, writer: &mut dyn WriteColor) -> Result<(), Error> {
-        match &self.kind {
-            MaybeSpanned::WithoutSpan(_) => {
-                self.write_description(writer)?;
-            }
+    pub fn new<T>(level: MessageLevel, kind: &'msg T) -> Self
+    where
+        T: Printable<'file, 'msg> + ?Sized,
+    {
+        Self {
+            level,
+            kind: kind.as_maybe_spanned(),
+            code: None,
+            labels: Vec::new(),
+            footers: Vec::new(),
+        }
+    }
+
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_label(mut self, span: Span<'file>, text: impl Into<String>) -> Self {
+        self.labels.push(Spanned {
+            span,
+            data: text.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, text: impl Into<String>) -> Self {
+        self.footers.push(Footer::Note(text.into()));
+        self
+    }
+
+    pub fn with_help(mut self, text: impl Into<String>) -> Self {
+        self.footers.push(Footer::Help(text.into()));
+        self
+    }
 
-            MaybeSpanned::WithSpan(spanned) => {
-                self.write_description(writer)?;
-                self.write_code(writer, &spanned.span)?;
+    pub fn write(
+        &self,
+        writer: &mut dyn WriteColor,
+        options: &SnippetOptions,
+    ) -> Result<(), Error> {
+        self.write_description(writer)?;
+
+        let annotations = self.annotations();
+        let lines = snippet_lines(&annotations, options);
+        let num_fmt = LineNumberFormatter::new(lines.last().map_or(0, |(number, _)| *number));
+        if !lines.is_empty() {
+            self.write_code(writer, &num_fmt, &lines, &annotations, options)?;
+        }
+
+        for footer in &self.footers {
+            if !lines.is_empty() {
+                num_fmt.equals(writer)?;
             }
+            let mut output = ColorOutput::new(writer);
+            output.set_bold(true);
+            write!(output.writer(), "{}: ", footer.name())?;
+            output.set_bold(false);
+            writeln!(output.writer(), "{}", footer.text())?;
         }
 
         writeln!(writer)?;
         Ok(())
     }
 
+    /// Write the message as a single line JSON object, e.g.
+    ///
+    /// ```json
+    /// {"type":"diagnostic","level":"error","code":"E0101","message":"...","span":{
+    ///     "start":{"line":1,"column":1,"offset":0},
+    ///     "end":{"line":1,"column":4,"offset":3}},
+    ///  "labels":[{"message":"...","span":{...}}],
+    ///  "footers":[{"level":"note","message":"..."}]}
+    /// ```
+    ///
+    /// Lines and columns start at 1, byte offsets at 0. `end` is the last
+    /// character of the span, i.e. it is inclusive. Messages without a code
+    /// have `"code":null`, messages without a span `"span":null`.
+    pub fn write_json(&self, writer: &mut dyn WriteColor) -> Result<(), Error> {
+        write!(
+            writer,
+            r#"{{"type":"diagnostic","level":{},"code":"#,
+            JsonString(self.level.name()),
+        )?;
+        match self.code {
+            None => write!(writer, "null")?,
+            Some(code) => write!(writer, "{}", JsonString(&code.to_string()))?,
+        }
+        write!(
+            writer,
+            r#","message":{},"span":"#,
+            JsonString(&(*self.kind).to_string()),
+        )?;
+
+        match &self.kind {
+            MaybeSpanned::WithoutSpan(_) => write!(writer, "null")?,
+            MaybeSpanned::WithSpan(spanned) => write!(writer, "{}", JsonSpan(&spanned.span))?,
+        }
+
+        write!(writer, r#","labels":["#)?;
+        for (i, label) in self.labels.iter().enumerate() {
+            write!(
+                writer,
+                r#"{}{{"message":{},"span":{}}}"#,
+                if i == 0 { "" } else { "," },
+                JsonString(&label.data),
+                JsonSpan(&label.span),
+            )?;
+        }
+
+        write!(writer, r#"],"footers":["#)?;
+        for (i, footer) in self.footers.iter().enumerate() {
+            write!(
+                writer,
+                r#"{}{{"level":{},"message":{}}}"#,
+                if i == 0 { "" } else { "," },
+                JsonString(footer.name()),
+                JsonString(footer.text()),
+            )?;
+        }
+
+        writeln!(writer, "]}}")?;
+        Ok(())
+    }
+
     fn write_description(&self, writer: &mut dyn WriteColor) -> Result<(), Error> {
         let mut output = ColorOutput::new(writer);
         output.set_color(self.level.color());
         output.set_bold(true);
-        write!(output.writer(), "{}: ", self.level.name())?;
+        write!(output.writer(), "{}", self.level.name())?;
+        if let Some(code) = self.code {
+            write!(output.writer(), "[{}]", code)?;
+        }
+        write!(output.writer(), ": ")?;
 
         output.set_color(None);
         writeln!(output.writer(), "{}", *self.kind)?;
@@ -249,49 +583,381 @@ impl<'file, 'msg> Message<'file, 'msg> {
         Ok(())
     }
 
-    fn write_code(&self, writer: &mut dyn WriteColor, error: &Span<'_>) -> Result<(), Error> {
-        let mut output = ColorOutput::new(writer);
-        let num_fmt = LineNumberFormatter::new(error);
+    fn annotations(&self) -> Vec<Annotation<'_, 'file>> {
+        let primary = match &self.kind {
+            MaybeSpanned::WithSpan(spanned) => Some(Annotation {
+                span: &spanned.span,
+                is_primary: true,
+                text: None,
+            }),
+            MaybeSpanned::WithoutSpan(_) => None,
+        };
+        let labels = self.labels.iter().map(|label| Annotation {
+            span: &label.span,
+            is_primary: false,
+            text: Some(&label.data),
+        });
+
+        primary.into_iter().chain(labels).collect()
+    }
 
-        num_fmt.spaces(output.writer())?;
-        writeln!(output.writer())?;
+    /// Write the lines of the snippet in source order. Each line with
+    /// annotations is followed by a single line that underlines all of them.
+    /// Lines that are not adjacent are separated by `...`.
+    fn write_code(
+        &self,
+        writer: &mut dyn WriteColor,
+        num_fmt: &LineNumberFormatter,
+        lines: &[(usize, Span<'_>)],
+        annotations: &[Annotation<'_, '_>],
+        options: &SnippetOptions,
+    ) -> Result<(), Error> {
+        let line_fmts: Vec<_> = lines
+            .iter()
+            .map(|(_, line)| LineFormatter::new(line))
+            .collect();
+        let marks: Vec<_> = line_fmts
+            .iter()
+            .map(|line_fmt| Mark::all(line_fmt, annotations))
+            .collect();
+        let windows = windows(&line_fmts, &marks, options.max_line_width);
+
+        num_fmt.spaces(writer)?;
+        writeln!(writer)?;
+
+        let mut prev_line_number = None;
+        for (i, (line_number, _)) in lines.iter().enumerate() {
+            if prev_line_number.map_or(false, |prev| prev + 1 < *line_number) {
+                num_fmt.ellipsis(writer)?;
+            }
+            prev_line_number = Some(*line_number);
+
+            num_fmt.number(writer, *line_number)?;
+            line_fmts[i].render(writer, &windows[i])?;
+            if !marks[i].is_empty() {
+                self.write_underlines(writer, num_fmt, &line_fmts[i], &marks[i], &windows[i])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Underline the visible parts of the marks of a line. The text of a
+    /// label follows the underline if nothing else is underlined to the
+    /// right of it. The texts of other labels are written on separate lines
+    /// below it.
+    fn write_underlines(
+        &self,
+        writer: &mut dyn WriteColor,
+        num_fmt: &LineNumberFormatter,
+        line_fmt: &LineFormatter<'_, '_>,
+        marks: &[Mark<'_, '_, '_>],
+        window: &Window,
+    ) -> Result<(), Error> {
+        // the underline of the primary span wins where spans overlap
+        let visible: Vec<_> = marks
+            .iter()
+            .filter_map(|mark| {
+                let (start, end) = window.visible(mark.start, mark.end)?;
+                Some((window.column(start), window.column(end), mark.annotation))
+            })
+            .collect();
+        let width = visible.iter().map(|(_, end, _)| *end).max().unwrap_or(0);
+        let mut underline: Vec<Option<bool>> = vec![None; width];
+        for (start, end, annotation) in &visible {
+            for cell in &mut underline[*start..*end] {
+                *cell = Some(cell.unwrap_or(false) || annotation.is_primary);
+            }
+        }
+
+        // the text of a label is written on the last line of its span
+        let mut texts: Vec<(usize, usize, &str)> = visible
+            .iter()
+            .filter(|(_, _, annotation)| line_fmt.contains(&annotation.span.end_position()))
+            .filter_map(|(start, end, annotation)| annotation.text.map(|text| (*start, *end, text)))
+            .collect();
+        texts.sort_by_key(|(_, end, _)| *end);
+
+        num_fmt.spaces(writer)?;
+        let mut cells = &underline[..];
+        while let Some(&cell) = cells.first() {
+            let run = cells.iter().take_while(|&&other| other == cell).count();
+            let mut output = ColorOutput::new(writer);
+            let chr = match cell {
+                None => " ",
+                Some(true) => {
+                    output.set_color(self.level.color());
+                    output.set_bold(true);
+                    "^"
+                }
+                Some(false) => {
+                    output.set_color(HIGHLIGHT_COLOR);
+                    output.set_bold(true);
+                    "-"
+                }
+            };
+            write!(output.writer(), "{}", chr.repeat(run))?;
+            cells = &cells[run..];
+        }
+        if texts.last().map_or(false, |(_, end, _)| *end == width) {
+            let (_, _, text) = texts.pop().unwrap();
+            let mut output = ColorOutput::new(writer);
+            output.set_color(HIGHLIGHT_COLOR);
+            output.set_bold(true);
+            write!(output.writer(), " {}", text)?;
+        }
+        writeln!(writer)?;
+
+        for (start, _, text) in texts.into_iter().rev() {
+            num_fmt.spaces(writer)?;
+            let mut output = ColorOutput::new(writer);
+            output.set_color(HIGHLIGHT_COLOR);
+            output.set_bold(true);
+            writeln!(output.writer(), "{}{}", " ".repeat(start), text)?;
+        }
+
+        Ok(())
+    }
+}
 
-        for (line_number, line) in error.lines().numbered() {
-            let line_fmt = LineFormatter::new(&line);
+/// The part of a line covered by an annotation, in terminal columns.
+struct Mark<'a, 'b, 'file> {
+    start: usize,
+    end: usize,
+    annotation: &'a Annotation<'b, 'file>,
+}
 
-            num_fmt.number(output.writer(), line_number)?;
-            line_fmt.render(output.writer())?;
-            // currently, the span will always exist since we take the line from the error
-            // but future versions may print a line below and above for context that
-            // is not part of the error
-            if let Some(faulty_part_of_line) = Span::intersect(error, &line) {
+impl<'a, 'b, 'file> Mark<'a, 'b, 'file> {
+    /// The marks of all annotations on the line, ordered by their start.
+    fn all(
+        line_fmt: &LineFormatter<'_, '_>,
+        annotations: &'a [Annotation<'b, 'file>],
+    ) -> Vec<Self> {
+        let mut marks: Vec<_> = annotations
+            .iter()
+            .filter_map(|annotation| {
+                let part = Span::intersect(annotation.span, line_fmt.line)?;
                 // TODO: implement this without the following 3 assumptions:
                 // - start_pos - end_pos >= 0, guranteed by data structure invariant of Span
                 // - start_term_pos - end_term_pos >= 0, guranteed by monotony of columns
                 //   (a Position.char() can only be rendered to 0 or more terminal characters)
                 // - unwrap(.): both positions are guranteed to exist in the line since we just
                 //   got them from the faulty line, which is a subset of the whole error line
-                let (start_term_pos, end_term_pos) =
-                    line_fmt.get_actual_columns(&faulty_part_of_line).unwrap();
+                let (start, end) = line_fmt.get_actual_columns(&part)?;
+                Some(Mark {
+                    start,
+                    end,
+                    annotation,
+                })
+            })
+            .collect();
+        marks.sort_by_key(|mark| mark.start);
+        marks
+    }
+}
 
-                let term_width = end_term_pos - start_term_pos;
+/// The lines of the snippet of a message with their line numbers, in source
+/// order: all lines covered by the annotations, without the middle of long
+/// spans, and `options.context_lines` lines above and below them.
+fn snippet_lines<'file>(
+    annotations: &[Annotation<'_, 'file>],
+    options: &SnippetOptions,
+) -> Vec<(usize, Span<'file>)> {
+    let mut lines: Vec<Span<'file>> = Vec::new();
+    for annotation in annotations {
+        let covered: Vec<Span<'file>> = annotation.span.lines().collect();
+        let kept = options.max_span_lines / 2;
+        let mut above = covered[0].clone();
+        let mut below = covered[covered.len() - 1].clone();
+
+        if covered.len() > options.max_span_lines {
+            lines.extend_from_slice(&covered[..kept]);
+            lines.extend_from_slice(&covered[covered.len() - kept..]);
+        } else {
+            lines.extend(covered);
+        }
 
-                num_fmt.spaces(output.writer())?;
+        for _ in 0..options.context_lines {
+            if let Some(line) = above.start_position().prev().map(|end| end.get_line()) {
+                lines.push(line.clone());
+                above = line;
+            }
+            if let Some(line) = below.end_position().next().map(|start| start.get_line()) {
+                lines.push(line.clone());
+                below = line;
+            }
+        }
+    }
 
-                {
-                    let mut output = ColorOutput::new(output.writer());
-                    output.set_color(self.level.color());
-                    output.set_bold(true);
-                    writeln!(
-                        output.writer(),
-                        "{spaces}{underline}",
-                        spaces = " ".repeat(start_term_pos),
-                        underline = "^".repeat(term_width)
-                    )?;
-                }
+    let mut lines: Vec<(usize, Span<'file>)> = lines
+        .into_iter()
+        .map(|line| (line_number(&line), line))
+        .collect();
+    lines.sort_by_key(|(line_number, _)| *line_number);
+    lines.dedup_by_key(|(line_number, _)| *line_number);
+    lines
+}
+
+/// The line number of a line as returned by `Position::get_line`. An empty
+/// line starts at its newline, which belongs to the next row, so the number
+/// is taken from the end of the line instead.
+fn line_number(line: &Span<'_>) -> usize {
+    let end = line.end_position();
+    if end.chr() == '\n' {
+        end.row()
+    } else {
+        end.line_number()
+    }
+}
+
+/// The part of a line that is written, in terminal columns. Lines longer
+/// than the maximum width are truncated, which is indicated by `...`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Window {
+    start: usize,
+    end: usize,
+    line_width: usize,
+}
+
+impl Window {
+    const ELLIPSIS: &'static str = "...";
+
+    /// The window of a line with the marked columns `marked`. The marked
+    /// columns are centered if the line must be truncated.
+    fn new(line_width: usize, marked: Option<(usize, usize)>, max_width: usize) -> Self {
+        if line_width <= max_width {
+            return Window {
+                start: 0,
+                end: line_width,
+                line_width,
+            };
+        }
+
+        let (marked_start, marked_end) = marked.unwrap_or((0, 0));
+        let slack = max_width.saturating_sub(marked_end - marked_start);
+        let start = marked_start
+            .saturating_sub(slack / 2)
+            .min(line_width - max_width);
+        Window {
+            start,
+            end: start + max_width,
+            line_width,
+        }
+    }
+
+    fn is_cut_at_start(&self) -> bool {
+        self.start > 0
+    }
+
+    fn is_cut_at_end(&self) -> bool {
+        self.end < self.line_width
+    }
+
+    /// The visible part of the columns `start..end`, if any
+    fn visible(&self, start: usize, end: usize) -> Option<(usize, usize)> {
+        let (start, end) = (start.max(self.start), end.min(self.end));
+        if start < end {
+            Some((start, end))
+        } else {
+            None
+        }
+    }
+
+    /// The column at which column `column` of the line is written
+    fn column(&self, column: usize) -> usize {
+        let prefix = if self.is_cut_at_start() {
+            Self::ELLIPSIS.len()
+        } else {
+            0
+        };
+        column - self.start + prefix
+    }
+}
+
+/// The windows of the lines of a snippet. A line without marks uses the
+/// window of the closest line with marks, so that the context of the marks
+/// stays visible.
+fn windows(
+    line_fmts: &[LineFormatter<'_, '_>],
+    marks: &[Vec<Mark<'_, '_, '_>>],
+    max_width: usize,
+) -> Vec<Window> {
+    let marked: Vec<Option<(usize, usize)>> = marks
+        .iter()
+        .map(|marks| {
+            let start = marks.iter().map(|mark| mark.start).min()?;
+            let end = marks.iter().map(|mark| mark.end).max()?;
+            Some((start, end))
+        })
+        .collect();
+
+    line_fmts
+        .iter()
+        .enumerate()
+        .map(|(i, line_fmt)| {
+            let closest = (0..marked.len())
+                .filter(|&j| marked[j].is_some())
+                .min_by_key(|&j| if i < j { j - i } else { i - j });
+            let marked = closest.and_then(|j| marked[j]);
+            Window::new(line_fmt.width(), marked, max_width)
+        })
+        .collect()
+}
+
+/// Writes a string as a quoted and escaped JSON string.
+struct JsonString<'s>(&'s str);
+
+impl Display for JsonString<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\"")?;
+        for chr in self.0.chars() {
+            match chr {
+                '"' => write!(f, "\\\"")?,
+                '\\' => write!(f, "\\\\")?,
+                '\n' => write!(f, "\\n")?,
+                '\r' => write!(f, "\\r")?,
+                '\t' => write!(f, "\\t")?,
+                chr if chr.is_control() => write!(f, "\\u{:04x}", chr as u32)?,
+                chr => write!(f, "{}", chr)?,
             }
         }
-        Ok(())
+        write!(f, "\"")
+    }
+}
+
+/// Writes a span as a JSON object with the positions of its first and last
+/// character.
+struct JsonSpan<'s, 'f>(&'s Span<'f>);
+
+impl Display for JsonSpan<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            r#"{{"start":{},"end":{}}}"#,
+            JsonPosition(&self.0.start_position()),
+            JsonPosition(&self.0.end_position()),
+        )
+    }
+}
+
+/// Writes a position as a JSON object with 1-based line and column and the
+/// 0-based byte offset.
+///
+/// Line and column are computed from the byte offset, because `Position`
+/// counts a line break as the first character of the following line.
+struct JsonPosition<'p, 'f>(&'p Position<'f>);
+
+impl Display for JsonPosition<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let offset = self.0.byte_offset();
+        let before = &self.0.file()[..offset];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        write!(
+            f,
+            r#"{{"line":{},"column":{},"offset":{}}}"#,
+            before.matches('\n').count() + 1,
+            offset - line_start + 1,
+            offset,
+        )
     }
 }
 
@@ -302,9 +968,10 @@ struct LineNumberFormatter {
 }
 
 impl LineNumberFormatter {
-    pub fn new(span: &Span<'_>) -> Self {
+    /// A formatter for line numbers up to `max_line_number`
+    pub fn new(max_line_number: usize) -> Self {
         Self {
-            width: span.end_position().line_number().to_string().len(),
+            width: max_line_number.to_string().len(),
         }
     }
 
@@ -324,6 +991,24 @@ impl LineNumberFormatter {
         write!(output.writer(), " {} | ", padded_number)?;
         Ok(())
     }
+
+    /// Separates lines that are not adjacent
+    pub fn ellipsis(&self, writer: &mut dyn WriteColor) -> Result<(), Error> {
+        let mut output = ColorOutput::new(writer);
+        output.set_color(HIGHLIGHT_COLOR);
+        output.set_bold(true);
+        writeln!(output.writer(), "...")?;
+        Ok(())
+    }
+
+    /// Precedes `note:` and `help:` lines below the code
+    pub fn equals(&self, writer: &mut dyn WriteColor) -> Result<(), Error> {
+        let mut output = ColorOutput::new(writer);
+        output.set_color(HIGHLIGHT_COLOR);
+        output.set_bold(true);
+        write!(output.writer(), " {} = ", " ".repeat(self.width))?;
+        Ok(())
+    }
 }
 
 pub fn pad_left(s: &str, pad: usize) -> String {
@@ -351,27 +1036,58 @@ impl<'span, 'file> LineFormatter<'span, 'file> {
         Self { line }
     }
 
-    fn render(&self, writer: &mut dyn WriteColor) -> Result<(), Error> {
+    fn contains(&self, position: &Position<'_>) -> bool {
+        let offset = position.byte_offset();
+        self.line.start_position().byte_offset() <= offset
+            && offset <= self.line.end_position().byte_offset()
+    }
+
+    /// Write the part of the line inside of `window`.
+    fn render(&self, writer: &mut dyn WriteColor, window: &Window) -> Result<(), Error> {
         let mut output = ColorOutput::new(writer);
 
+        if window.is_cut_at_start() {
+            output.set_color(HIGHLIGHT_COLOR);
+            write!(output.writer(), "{}", Window::ELLIPSIS)?;
+        }
+
         // TODO: implement an iterator
         let chars = self.line.start_position().iter();
 
+        let mut column = 0;
         for position in chars {
             let (text, color) = self.render_char(position.chr());
-            output.set_color(color);
-            write!(output.writer(), "{}", text)?;
+            if window.start <= column && column + text.len() <= window.end {
+                output.set_color(color);
+                write!(output.writer(), "{}", text)?;
+            }
+            column += text.len();
 
             if position == self.line.end_position() {
                 break;
             }
         }
 
+        if window.is_cut_at_end() {
+            output.set_color(HIGHLIGHT_COLOR);
+            write!(output.writer(), "{}", Window::ELLIPSIS)?;
+        }
+
         writeln!(output.writer())?;
 
         Ok(())
     }
 
+    /// The number of terminal columns the line takes up
+    fn width(&self) -> usize {
+        self.line
+            .start_position()
+            .iter()
+            .take_while(|position| *position <= self.line.end_position())
+            .map(|position| self.render_char(position.chr()).0.len())
+            .sum()
+    }
+
     /// Map terminal columns to `Position` columns. Returns a inclusive
     /// lower bound, and an exclusive upper bound.
     ///
@@ -444,6 +1160,7 @@ impl<'span, 'file> LineFormatter<'span, 'file> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::asciifile::AsciiFile;
 
     #[test]
     fn test_pad_left() {
@@ -457,4 +1174,203 @@ mod tests {
         // not enough padding does not truncate string
         assert_eq!("a", pad_left("a", 0));
     }
+
+    #[test]
+    fn json_messages_are_single_lines() {
+        let file = AsciiFile::new(b"class Foo {\n    \"x\";\n}").unwrap();
+        let positions: Vec<_> = file.iter().collect();
+        let message = "unexpected \"x\"";
+        let spanned = Spanned {
+            span: Span::new(positions[16], positions[18]),
+            data: message,
+        };
+
+        let mut buffer = termcolor::Buffer::no_color();
+        Message::new(MessageLevel::Error, &spanned)
+            .with_code(ErrorCode(201))
+            .with_label(Span::new(positions[6], positions[8]), "in this class")
+            .with_help("remove it")
+            .write_json(&mut buffer)
+            .unwrap();
+        Message::new(MessageLevel::Warning, &"tab\there")
+            .write_json(&mut buffer)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(buffer.into_inner()).unwrap(),
+            "{\"type\":\"diagnostic\",\"level\":\"error\",\"code\":\"E0201\",\
+             \"message\":\"unexpected \\\"x\\\"\",\"span\":{\
+             \"start\":{\"line\":2,\"column\":5,\"offset\":16},\
+             \"end\":{\"line\":2,\"column\":7,\"offset\":18}},\
+             \"labels\":[{\"message\":\"in this class\",\"span\":{\
+             \"start\":{\"line\":1,\"column\":7,\"offset\":6},\
+             \"end\":{\"line\":1,\"column\":9,\"offset\":8}}}],\
+             \"footers\":[{\"level\":\"help\",\"message\":\"remove it\"}]}\n\
+             {\"type\":\"diagnostic\",\"level\":\"warning\",\"code\":null,\
+             \"message\":\"tab\\there\",\"span\":null,\"labels\":[],\"footers\":[]}\n"
+        );
+    }
+
+    #[test]
+    fn labels_are_rendered_in_source_order() {
+        let source = "int a;\nint b = a;\n\n\n\nint a = b + a;\n";
+        let file = AsciiFile::new(source.as_bytes()).unwrap();
+        let positions: Vec<_> = file.iter().collect();
+        let span = |from: &str, nth: usize, len: usize| {
+            let start = source.match_indices(from).nth(nth).unwrap().0;
+            Span::new(positions[start], positions[start + len - 1])
+        };
+        let error = Spanned {
+            span: span("int a", 1, 5),
+            data: "redefinition of 'a'",
+        };
+
+        let mut buffer = termcolor::Buffer::no_color();
+        Message::new(MessageLevel::Error, &error)
+            .with_label(span("b", 1, 1), "'b' is used here")
+            .with_label(span("a", 3, 1), "and 'a' here")
+            .with_label(span("int a", 0, 5), "first declared here")
+            .with_note("variables are declared once")
+            .write(&mut buffer, &SnippetOptions::default())
+            .unwrap();
+
+        let expected = [
+            "error: redefinition of 'a'",
+            "   | ",
+            " 1 | int a;",
+            "   | ----- first declared here",
+            "...",
+            " 6 | int a = b + a;",
+            "   | ^^^^^   -   - and 'a' here",
+            "   |         'b' is used here",
+            "   = note: variables are declared once",
+            "",
+            "",
+        ];
+        assert_eq!(
+            String::from_utf8(buffer.into_inner()).unwrap(),
+            expected.join("\n")
+        );
+    }
+
+    fn render(source: &str, from: &str, to: &str, options: &SnippetOptions) -> String {
+        let file = AsciiFile::new(source.as_bytes()).unwrap();
+        let positions: Vec<_> = file.iter().collect();
+        let error = Spanned {
+            span: Span::new(
+                positions[source.find(from).unwrap()],
+                positions[source.find(to).unwrap() + to.len() - 1],
+            ),
+            data: "invalid",
+        };
+
+        let mut buffer = termcolor::Buffer::no_color();
+        Message::new(MessageLevel::Error, &error)
+            .write(&mut buffer, options)
+            .unwrap();
+        String::from_utf8(buffer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn long_spans_are_elided_and_surrounded_by_context() {
+        let source = "a\n\nb1\nb2\nb3\nb4\nb5\nc\nd\n";
+        let options = SnippetOptions {
+            context_lines: 2,
+            ..SnippetOptions::default()
+        };
+
+        let expected = [
+            "error: invalid",
+            "   | ",
+            " 1 | a",
+            " 2 | ",
+            " 3 | b1",
+            "   | ^^",
+            " 4 | b2",
+            "   | ^^",
+            "...",
+            " 6 | b4",
+            "   | ^^",
+            " 7 | b5",
+            "   | ^^",
+            " 8 | c",
+            " 9 | d",
+            "",
+            "",
+        ];
+        assert_eq!(render(source, "b1", "b5", &options), expected.join("\n"));
+    }
+
+    #[test]
+    fn long_lines_are_truncated_around_the_underline() {
+        let source = "0123456789abcdefghijklmnopqrstuvwxyz\n";
+        let options = SnippetOptions {
+            max_line_width: 16,
+            ..SnippetOptions::default()
+        };
+
+        let expected = [
+            "error: invalid",
+            "   | ",
+            " 1 | ...abcdefghijklmnop...",
+            "   |           ^^",
+            "",
+            "",
+        ];
+        assert_eq!(render(source, "hi", "hi", &options), expected.join("\n"));
+
+        let expected = [
+            "error: invalid",
+            "   | ",
+            " 1 | 0123456789abcdef...",
+            "   |  ^",
+            "",
+            "",
+        ];
+        assert_eq!(render(source, "1", "1", &options), expected.join("\n"));
+    }
+
+    #[test]
+    fn lint_levels_decide_how_warnings_are_reported() {
+        use crate::{
+            context::SharedBuffer,
+            lints::{self, LintSelector},
+            sem::SemanticWarning,
+        };
+
+        let buffer = SharedBuffer::default();
+        let mut diagnostics = Diagnostics::new(box buffer.clone());
+        let warning = MaybeSpanned::WithoutSpan(SemanticWarning::UnreachableStatement);
+        let unreachable = LintSelector::Lint(&lints::UNREACHABLE_STATEMENT);
+
+        diagnostics.lint(&warning);
+        assert_eq!(diagnostics.count(MessageLevel::Warning), 1);
+        assert!(!diagnostics.errored());
+        diagnostics.lint(&warning);
+        assert_eq!(diagnostics.count(MessageLevel::Warning), 2);
+        // only the first warning of a lint has a note
+        assert_eq!(buffer.contents().matches("note:").count(), 1);
+
+        diagnostics.set_lint_level(LintSelector::All, LintLevel::Allow);
+        diagnostics.lint(&warning);
+        assert_eq!(diagnostics.count(MessageLevel::Warning), 2);
+
+        // a level for a single lint wins over the level for all lints
+        diagnostics.set_lint_level(LintSelector::All, LintLevel::Deny);
+        diagnostics.set_lint_level(unreachable, LintLevel::Warn);
+        diagnostics.lint(&warning);
+        assert_eq!(diagnostics.count(MessageLevel::Warning), 3);
+        assert!(!diagnostics.errored());
+
+        diagnostics.set_lint_level(unreachable, LintLevel::Deny);
+        diagnostics.lint(&warning);
+        diagnostics.error(&"not a warning");
+        assert!(diagnostics.errored());
+        assert_eq!(diagnostics.count(MessageLevel::Error), 2);
+
+        diagnostics.write_statistics();
+        let output = buffer.contents();
+        assert!(output.contains("error: unreachable statement\n"));
+        assert!(output.ends_with("Compilation aborted due to an error and a denied warning\n"));
+    }
 }
@@ -0,0 +1,608 @@
+//! Stable codes of the errors reported by the lexer, the parser and the
+//! semantic analysis, and the registry of their explanations.
+//!
+//! Every code is printed in the header of its error message, e.g.
+//! `error[E0101]: unclosed comment`. `comprakt --explain E0101` prints the
+//! explanation of the code. The first two digits of a code identify the
+//! compiler stage that reports it:
+//!
+//! - `E01xx`: lexer, see `lexer::ErrorKind`
+//! - `E02xx`: parser, see `parser::SyntaxError`
+//! - `E03xx`: semantic analysis, see `sem::SemanticError`
+//!
+//! Codes are never reused. New error kinds get the next free code of their
+//! stage and an entry in `EXPLANATIONS`.
+use crate::asciifile::{MaybeSpanned, Spanned};
+use failure::Fail;
+use std::{fmt, str::FromStr};
+
+/// The code of an error, written as `E` followed by four digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ErrorCode(pub u16);
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "E{:04}", self.0)
+    }
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "invalid error code '{}', expected e.g. 'E0101'", code)]
+pub struct InvalidErrorCode {
+    code: String,
+}
+
+impl FromStr for ErrorCode {
+    type Err = InvalidErrorCode;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidErrorCode {
+            code: code.to_string(),
+        };
+        if code.len() != 5 || !(code.starts_with('E') || code.starts_with('e')) {
+            return Err(invalid());
+        }
+        code[1..].parse().map(ErrorCode).map_err(|_| invalid())
+    }
+}
+
+/// Errors that have a code.
+pub trait Coded {
+    fn code(&self) -> ErrorCode;
+}
+
+impl<'f, T: Coded> Coded for Spanned<'f, T> {
+    fn code(&self) -> ErrorCode {
+        self.data.code()
+    }
+}
+
+impl<'f, T: Coded> Coded for MaybeSpanned<'f, T> {
+    fn code(&self) -> ErrorCode {
+        (**self).code()
+    }
+}
+
+/// The explanation of an error code, printed by `--explain`.
+#[derive(Debug)]
+pub struct Explanation {
+    pub code: ErrorCode,
+    /// A short summary, similar to the error message
+    pub title: &'static str,
+    /// What causes the error and how to fix it
+    pub description: &'static str,
+    /// A minimal MiniJava program that causes the error
+    pub example: &'static str,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}: {}", self.code, self.title)?;
+        writeln!(f)?;
+        writeln!(f, "{}", self.description)?;
+        writeln!(f)?;
+        writeln!(f, "Example:")?;
+        writeln!(f)?;
+        for line in self.example.lines() {
+            if line.is_empty() {
+                writeln!(f)?;
+            } else {
+                writeln!(f, "    {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The explanation of `code`, if the code exists.
+pub fn explain(code: ErrorCode) -> Option<&'static Explanation> {
+    EXPLANATIONS
+        .iter()
+        .find(|explanation| explanation.code == code)
+}
+
+/// The explanations of all codes, ordered by code.
+#[rustfmt::skip]
+pub const EXPLANATIONS: &[Explanation] = &[
+    Explanation {
+        code: ErrorCode(101),
+        title: "unclosed comment",
+        description: "\
+A comment starting with `/*` was not closed by `*/` before the end of the
+file. Comments cannot be nested, so the first `*/` closes the comment.",
+        example: "\
+class Main {
+    public static void main(String[] args) {}
+}
+/* this comment is never closed",
+    },
+    Explanation {
+        code: ErrorCode(102),
+        title: "unexpected character",
+        description: "\
+The character is not part of any token of MiniJava. MiniJava has neither
+character nor string literals, so quotes are not allowed outside of
+comments.",
+        example: "\
+class Main {
+    public static void main(String[] args) {
+        System.out.println('a');
+    }
+}",
+    },
+    Explanation {
+        code: ErrorCode(201),
+        title: "unexpected token",
+        description: "\
+The token is not allowed at this position. The message lists every token
+the parser expected instead. A frequent cause is a missing `;` or `}`.",
+        example: "\
+class Main {
+    public int x
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(202),
+        title: "unexpected end of file",
+        description: "\
+The file ended in the middle of a class, e.g. because a `}` is missing.",
+        example: "\
+class Main {
+    public static void main(String[] args) {}",
+    },
+    Explanation {
+        code: ErrorCode(301),
+        title: "redefinition of a class",
+        description: "\
+Two classes of the program have the same name. Rename one of them.",
+        example: "\
+class A {}
+class A {}
+class Main {
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(302),
+        title: "redefinition of a field",
+        description: "\
+A class declares two fields with the same name, even if their types are
+different. Rename one of them.",
+        example: "\
+class Main {
+    public int x;
+    public boolean x;
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(303),
+        title: "redefinition of a method",
+        description: "\
+A class declares two methods with the same name. MiniJava does not support
+overloading, so methods with different parameters need different names.",
+        example: "\
+class Main {
+    public int f() { return 0; }
+    public int f(int x) { return x; }
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(304),
+        title: "redefinition of a parameter",
+        description: "\
+A method declares two parameters with the same name. Rename one of them.",
+        example: "\
+class Main {
+    public int f(int x, int x) { return x; }
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(305),
+        title: "redefinition of a local variable",
+        description: "\
+A local variable is declared while another local variable with the same
+name is in scope, e.g. in an enclosing block. Rename one of them.",
+        example: "\
+class Main {
+    public void f() {
+        int x;
+        { int x; }
+    }
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(306),
+        title: "local variable shadows a parameter",
+        description: "\
+A local variable has the same name as a parameter of its method, which
+would make the parameter inaccessible. Rename the variable.",
+        example: "\
+class Main {
+    public void f(int x) {
+        int x;
+    }
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(307),
+        title: "undeclared variable",
+        description: "\
+The name is neither a local variable, a parameter or a field of the class,
+nor a global like `System`. Declare it before it is used.",
+        example: "\
+class Main {
+    public int f() { return y; }
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(308),
+        title: "undeclared method",
+        description: "\
+The class of the object a method is called on has no method with this
+name.",
+        example: "\
+class Main {
+    public int f() { return g(); }
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(309),
+        title: "undeclared type",
+        description: "\
+The type is neither `int`, `boolean`, `void` nor a class of the program.",
+        example: "\
+class Main {
+    public Foo f;
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(310),
+        title: "undeclared field",
+        description: "\
+The class of the object a field is accessed on has no field with this
+name.",
+        example: "\
+class Main {
+    public int f() { return this.y; }
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(311),
+        title: "mismatched types",
+        description: "\
+The expression has a different type than required by its context, e.g. the
+type of a variable it is assigned to, or the return type of the method.
+MiniJava has no implicit conversions.",
+        example: "\
+class Main {
+    public int f() { return true; }
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(312),
+        title: "invalid comparison",
+        description: "\
+`==` and `!=` compare values of the same type. References can also be
+compared with `null`.",
+        example: "\
+class Main {
+    public boolean f() { return 1 == true; }
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(313),
+        title: "method call on a value that is not an object",
+        description: "\
+Methods can only be called on objects, not on values of type `int`,
+`boolean` or arrays.",
+        example: "\
+class Main {
+    public int f(int x) { return x.f(1); }
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(314),
+        title: "field access on a value that is not an object",
+        description: "\
+Fields can only be accessed on objects, not on values of type `int`,
+`boolean` or arrays. MiniJava arrays have no `length` field.",
+        example: "\
+class Main {
+    public int f(int[] xs) { return xs.length; }
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(315),
+        title: "index into a value that is not an array",
+        description: "\
+Only arrays can be indexed with `[]`.",
+        example: "\
+class Main {
+    public int f(int x) { return x[0]; }
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(316),
+        title: "wrong number of arguments",
+        description: "\
+A method is called with more or less arguments than it has parameters.",
+        example: "\
+class Main {
+    public int f(int x) { return f(); }
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(317),
+        title: "invalid left-hand side of an assignment",
+        description: "\
+Only variables, parameters, fields and array elements can be assigned to.",
+        example: "\
+class Main {
+    public void f() { 1 = 2; }
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(318),
+        title: "invalid use of `void`",
+        description: "\
+`void` is only allowed as the return type of a method. Fields, parameters,
+local variables and arrays cannot have the type `void`.",
+        example: "\
+class Main {
+    public void x;
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(319),
+        title: "missing return value",
+        description: "\
+A method with a return type other than `void` must return a value.",
+        example: "\
+class Main {
+    public int f() { return; }
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(320),
+        title: "return value in a method returning `void`",
+        description: "\
+A method with the return type `void` cannot return a value. Remove the
+value, or change the return type of the method.",
+        example: "\
+class Main {
+    public void f() { return 1; }
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(321),
+        title: "class shadows a built-in class",
+        description: "\
+The class `String` is built into MiniJava and cannot be redefined.",
+        example: "\
+class String {}
+class Main {
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(322),
+        title: "instantiation of a built-in class",
+        description: "\
+Built-in classes like `String` cannot be instantiated with `new`.",
+        example: "\
+class Main {
+    public void f() { String s = new String(); }
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(323),
+        title: "use of the parameter of the main method",
+        description: "\
+The parameter of the main method only exists for compatibility with Java.
+It cannot be used.",
+        example: "\
+class Main {
+    public static void main(String[] args) {
+        String[] copy = args;
+    }
+}",
+    },
+    Explanation {
+        code: ErrorCode(324),
+        title: "missing main method",
+        description: "\
+Every program must contain a method `public static void main(String[]
+args)`, which is called when the program starts.",
+        example: "\
+class Main {}",
+    },
+    Explanation {
+        code: ErrorCode(325),
+        title: "multiple main methods",
+        description: "\
+A program must contain exactly one main method, even if the main methods
+are declared in different classes.",
+        example: "\
+class A {
+    public static void main(String[] args) {}
+}
+class Main {
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(326),
+        title: "main method not named `main`",
+        description: "\
+The only static method of a program is its main method, so it must be named
+`main`.",
+        example: "\
+class Main {
+    public static void start(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(327),
+        title: "`this` in the main method",
+        description: "\
+The main method is static, so there is no object `this` could refer to.
+Create an object with `new` instead.",
+        example: "\
+class Main {
+    public static void main(String[] args) {
+        Main main = this;
+    }
+}",
+    },
+    Explanation {
+        code: ErrorCode(328),
+        title: "call of a non-static method in the main method",
+        description: "\
+The main method is static, so methods can only be called on objects created
+with `new`.",
+        example: "\
+class Main {
+    public void f() {}
+    public static void main(String[] args) {
+        f();
+    }
+}",
+    },
+    Explanation {
+        code: ErrorCode(329),
+        title: "use of a non-static field in the main method",
+        description: "\
+The main method is static, so fields can only be accessed on objects
+created with `new`.",
+        example: "\
+class Main {
+    public int x;
+    public static void main(String[] args) {
+        x = 1;
+    }
+}",
+    },
+    Explanation {
+        code: ErrorCode(330),
+        title: "missing return statement",
+        description: "\
+A method with a return type other than `void` can reach its end without
+returning a value, e.g. because an `if` has no `else` branch.",
+        example: "\
+class Main {
+    public int f(boolean b) {
+        if (b) return 1;
+    }
+    public static void main(String[] args) {}
+}",
+    },
+    Explanation {
+        code: ErrorCode(331),
+        title: "integer literal out of range",
+        description: "\
+Integer literals must fit into an `int`, i.e. be at most 2147483647. The
+only exception is -2147483648, where the literal is negated directly.",
+        example: "\
+class Main {
+    public int f() { return 2147483648; }
+    public static void main(String[] args) {}
+}",
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        asciifile::AsciiFile,
+        context::{Context, SharedBuffer},
+        diagnostics::ErrorFormat,
+        lexer::{Lexer, TokenKind},
+        parser::Parser,
+        sem,
+        strtab::StringTable,
+    };
+
+    /// The codes of all errors reported for `source`, without duplicates.
+    fn reported_codes(source: &str) -> Vec<String> {
+        let buffer = SharedBuffer::default();
+        let strtab = StringTable::new();
+        let file = AsciiFile::new(source.as_bytes()).unwrap();
+        let mut context = Context::new(&file, box buffer.clone());
+        context.diagnostics.set_format(ErrorFormat::Json);
+
+        let tokens = Lexer::new(&strtab, &context).filter_map(|result| match result {
+            Ok(token) => match token.data {
+                TokenKind::Whitespace | TokenKind::Comment(_) => None,
+                _ => Some(token),
+            },
+            Err(error) => {
+                context.diagnostics.coded_error(&error);
+                None
+            }
+        });
+        let ast = Parser::new(tokens).parse_with_diagnostics(&context.diagnostics);
+        if !context.diagnostics.errored() {
+            sem::check(&ast, &context).unwrap();
+        }
+
+        let mut codes: Vec<_> = buffer
+            .contents()
+            .split(r#""code":""#)
+            .skip(1)
+            .map(|rest| rest[..5].to_string())
+            .collect();
+        codes.dedup();
+        codes
+    }
+
+    #[test]
+    fn codes_are_parsed_and_printed() {
+        assert_eq!("E0101".parse::<ErrorCode>().unwrap(), ErrorCode(101));
+        assert_eq!("e0312".parse::<ErrorCode>().unwrap(), ErrorCode(312));
+        assert_eq!(ErrorCode(7).to_string(), "E0007");
+        assert!("E101".parse::<ErrorCode>().is_err());
+        assert!("X0101".parse::<ErrorCode>().is_err());
+        assert!("E01a1".parse::<ErrorCode>().is_err());
+    }
+
+    #[test]
+    fn explanations_are_ordered_by_code() {
+        for pair in EXPLANATIONS.windows(2) {
+            assert!(pair[0].code < pair[1].code, "{} is not unique", pair[1].code);
+        }
+        assert_eq!(explain(ErrorCode(101)).unwrap().title, "unclosed comment");
+        assert!(explain(ErrorCode(999)).is_none());
+    }
+
+    #[test]
+    fn examples_report_exactly_their_code() {
+        let mismatches: Vec<_> = EXPLANATIONS
+            .iter()
+            .map(|explanation| (explanation.code, reported_codes(explanation.example)))
+            .filter(|(code, reported)| *reported != vec![code.to_string()])
+            .collect();
+        assert!(mismatches.is_empty(), "{:?}", mismatches);
+    }
+}
@@ -0,0 +1,531 @@
+//! A tree-walking interpreter that executes a semantically checked program
+//! directly on the AST.
+//!
+//! The interpreter relies on the information computed by the semantic
+//! analysis: variables are looked up using the `NameResolution`, the value
+//! of integer literals is taken from `IntLiterals` and calls to the built-in
+//! `System.out` and `System.in` objects are recognized by their static type.
+//!
+//! Run-time errors like dereferencing `null`, accessing an array out of its
+//! bounds or dividing by zero abort the execution. They are reported to
+//! `context.diagnostics` with the span of the faulting expression.
+mod value;
+
+pub use self::value::{ArrayRef, Object, ObjectRef, Value};
+
+use crate::{
+    asciifile::{Span, Spanned},
+    ast,
+    context::Context,
+    sem::{BuiltinMethod, CheckedType, TypedProgram, VarDef},
+    strtab::Symbol,
+};
+use failure::Fail;
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+};
+
+/// Recursion deeper than this is reported as a stack overflow instead of
+/// crashing the interpreter.
+pub const MAX_CALL_DEPTH: usize = 10_000;
+
+/// The interpreter recurses on the native stack for every method call and
+/// every nested expression. It must run on a thread with a stack of this
+/// size to reach `MAX_CALL_DEPTH` without crashing, even in debug builds.
+pub const STACK_SIZE: usize = 1 << 30;
+
+#[rustfmt::skip]
+#[derive(Debug, Fail)]
+pub enum RuntimeError {
+    #[fail(display = "null pointer dereference")]
+    NullPointerDereference,
+    #[fail(display = "array index {} is out of bounds for length {}", index, length)]
+    ArrayIndexOutOfBounds { index: i32, length: usize },
+    #[fail(display = "cannot create an array of negative size {}", size)]
+    NegativeArraySize { size: i32 },
+    #[fail(display = "division by zero")]
+    DivisionByZero,
+    #[fail(display = "stack overflow: more than {} nested method calls", depth)]
+    StackOverflow { depth: usize },
+    #[fail(display = "input/output error: {}", message)]
+    Io { message: String },
+}
+
+/// Execute the main method of `program`, reading from `input` and writing
+/// to `output`. Run-time errors are reported to `context.diagnostics`.
+pub fn run<'a, 'f>(
+    program: &TypedProgram<'a, 'f>,
+    context: &Context<'_>,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) {
+    let mut interpreter = Interpreter {
+        program,
+        input,
+        output,
+        call_depth: 0,
+    };
+
+    let result = interpreter.run_main();
+    // output written before a run-time error must not get lost
+    let flushed = interpreter.flush();
+    if let Err(error) = result.and(flushed) {
+        context.diagnostics.error(&error);
+    }
+}
+
+/// The reasons to leave a sequence of statements early.
+enum Interrupt<'f> {
+    Return(Value),
+    Error(Spanned<'f, RuntimeError>),
+}
+
+impl<'f> From<Spanned<'f, RuntimeError>> for Interrupt<'f> {
+    fn from(error: Spanned<'f, RuntimeError>) -> Self {
+        Interrupt::Error(error)
+    }
+}
+
+type Flow<'f> = Result<(), Interrupt<'f>>;
+type Eval<'f, T = Value> = Result<T, Spanned<'f, RuntimeError>>;
+
+/// The variables of a method invocation.
+struct Frame {
+    /// The class declaring the method
+    class_name: Symbol,
+    /// `None` in the static main method
+    this: Option<ObjectRef>,
+    locals: HashMap<Symbol, Value>,
+}
+
+struct Interpreter<'p, 'a, 'f, 'io> {
+    program: &'p TypedProgram<'a, 'f>,
+    input: &'io mut dyn Read,
+    output: &'io mut dyn Write,
+    call_depth: usize,
+}
+
+impl<'p, 'a, 'f, 'io> Interpreter<'p, 'a, 'f, 'io> {
+    fn run_main(&mut self) -> Eval<'f, ()> {
+        let (class_name, main) = self
+            .program
+            .program
+            .classes
+            .iter()
+            .flat_map(|decl| decl.members.iter().map(move |member| (&decl.name, member)))
+            .find(|(_, member)| match member.kind {
+                ast::ClassMemberKind::MainMethod(..) => true,
+                _ => false,
+            })
+            .expect("semantic analysis guarantees a main method");
+
+        self.call(class_name, main, None, Vec::new()).map(|_| ())
+    }
+
+    fn flush(&mut self) -> Eval<'f, ()> {
+        self.output.flush().map_err(|err| {
+            // there is no better span for errors at the end of the program
+            let span = self.program.program.span.clone();
+            io_error(&span, &err)
+        })
+    }
+
+    fn exec_block(
+        &mut self,
+        frame: &mut Frame,
+        block: &'a Spanned<'f, ast::Block<'f>>,
+    ) -> Flow<'f> {
+        for stmt in &block.statements {
+            self.exec_stmt(frame, stmt)?;
+        }
+        Ok(())
+    }
+
+    fn exec_stmt(&mut self, frame: &mut Frame, stmt: &'a Spanned<'f, ast::Stmt<'f>>) -> Flow<'f> {
+        use crate::ast::Stmt::*;
+        match &stmt.data {
+            Block(block) => self.exec_block(frame, block)?,
+            Empty => (),
+            Error => unreachable!("programs with syntax errors are not run"),
+            If(cond, then_arm, else_arm) => {
+                if self.eval(frame, cond)?.as_bool() {
+                    self.exec_stmt(frame, then_arm)?;
+                } else if let Some(else_arm) = else_arm {
+                    self.exec_stmt(frame, else_arm)?;
+                }
+            }
+            While(cond, body) => {
+                while self.eval(frame, cond)?.as_bool() {
+                    self.exec_stmt(frame, body)?;
+                }
+            }
+            Expression(expr) => {
+                self.eval(frame, expr)?;
+            }
+            Return(expr) => {
+                let value = match expr {
+                    Some(expr) => self.eval(frame, expr)?,
+                    None => Value::Void,
+                };
+                return Err(Interrupt::Return(value));
+            }
+            LocalVariableDeclaration(ty, name, init) => {
+                let value = match init {
+                    Some(init) => self.eval(frame, init)?,
+                    None => Value::default_for(ty),
+                };
+                frame.locals.insert(name.clone(), value);
+            }
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::cyclomatic_complexity)]
+    fn eval(&mut self, frame: &mut Frame, expr: &'a Spanned<'f, ast::Expr<'f>>) -> Eval<'f> {
+        use crate::ast::{BinaryOp, Expr::*, UnaryOp};
+        Ok(match &expr.data {
+            Binary(BinaryOp::Assign, lhs, rhs) => self.eval_assignment(frame, lhs, rhs)?,
+            Binary(BinaryOp::LogicalAnd, lhs, rhs) => {
+                Value::Boolean(self.eval(frame, lhs)?.as_bool() && self.eval(frame, rhs)?.as_bool())
+            }
+            Binary(BinaryOp::LogicalOr, lhs, rhs) => {
+                Value::Boolean(self.eval(frame, lhs)?.as_bool() || self.eval(frame, rhs)?.as_bool())
+            }
+            Binary(op, lhs, rhs) => {
+                let lhs = self.eval(frame, lhs)?;
+                let rhs = self.eval(frame, rhs)?;
+                self.eval_binary_op(*op, &lhs, &rhs, &expr.span)?
+            }
+            Unary(UnaryOp::Not, operand) => Value::Boolean(!self.eval(frame, operand)?.as_bool()),
+            Unary(UnaryOp::Neg, operand) => {
+                Value::Int(self.eval(frame, operand)?.as_int().wrapping_neg())
+            }
+            MethodInvocation(target, name, args) => {
+                if let CheckedType::Builtin(class) = self.program.expr_type(target) {
+                    let method = class
+                        .method(name)
+                        .expect("type checker guarantees built-in methods exist");
+                    let args = self.eval_args(frame, args)?;
+                    return self.call_builtin(method, &args, &expr.span);
+                }
+
+                let target = self.eval(frame, target)?;
+                let args = self.eval_args(frame, args)?;
+                let object = match target {
+                    Value::Object(object) => object,
+                    _ => return Err(null_pointer(&expr.span)),
+                };
+                let class_name = object.borrow().class_name.clone();
+                self.call_method(&class_name, name, Some(object), args, &expr.span)?
+            }
+            FieldAccess(target, name) => {
+                if let CheckedType::Builtin(_) = self.program.expr_type(target) {
+                    return Ok(Value::Void);
+                }
+
+                match self.eval(frame, target)? {
+                    Value::Object(object) => object.borrow().fields[name].clone(),
+                    _ => return Err(null_pointer(&expr.span)),
+                }
+            }
+            ArrayAccess(target, index) => {
+                let array = self.eval(frame, target)?;
+                let index = self.eval(frame, index)?.as_int();
+                let array = expect_array(array, &expr.span)?;
+                load_element(&array, index, &expr.span)?
+            }
+            Null => Value::Null,
+            Boolean(value) => Value::Boolean(*value),
+            Int(_) => Value::Int(self.program.int_value(expr)),
+            Var(name) => match self.var_def(expr) {
+                VarDef::Field(_) => frame
+                    .this
+                    .as_ref()
+                    .expect("semantic analysis forbids fields in the main method")
+                    .borrow()
+                    .fields[name]
+                    .clone(),
+                VarDef::Param(_) | VarDef::Local(_) => frame.locals[name].clone(),
+                VarDef::Builtin(_) => Value::Void,
+                VarDef::MainParam(_) => unreachable!("the main parameter cannot be used"),
+            },
+            ThisMethodInvocation(name, args) => {
+                let args = self.eval_args(frame, args)?;
+                let this = frame.this.clone();
+                self.call_method(&frame.class_name, name, this, args, &expr.span)?
+            }
+            This => Value::Object(
+                frame
+                    .this
+                    .clone()
+                    .expect("semantic analysis forbids `this` in the main method"),
+            ),
+            NewObject(name) => {
+                let class = self
+                    .program
+                    .classes
+                    .get(name)
+                    .expect("semantic analysis guarantees the class exists");
+                let fields = class
+                    .fields
+                    .iter()
+                    .map(|(name, member)| match &member.kind {
+                        ast::ClassMemberKind::Field(ty) => (name.clone(), Value::default_for(ty)),
+                        _ => unreachable!("fields are declared by `ClassMemberKind::Field`"),
+                    })
+                    .collect();
+                Value::new_object(name.clone(), fields)
+            }
+            NewArray(basic, size, depth) => {
+                let size = self.eval(frame, size)?.as_int();
+                if size < 0 {
+                    return Err(Spanned {
+                        span: expr.span.clone(),
+                        data: RuntimeError::NegativeArraySize { size },
+                    });
+                }
+                let element = Value::default_for(&ast::Type {
+                    basic: basic.clone(),
+                    array_depth: *depth,
+                });
+                Value::new_array(vec![element; size as usize])
+            }
+        })
+    }
+
+    fn eval_assignment(
+        &mut self,
+        frame: &mut Frame,
+        lhs: &'a Spanned<'f, ast::Expr<'f>>,
+        rhs: &'a Spanned<'f, ast::Expr<'f>>,
+    ) -> Eval<'f> {
+        // Like in Java, the operands of the left hand side are evaluated
+        // before the right hand side, but null and bounds checks happen after
+        // evaluating the right hand side.
+        match &lhs.data {
+            ast::Expr::Var(name) => {
+                let value = self.eval(frame, rhs)?;
+                match self.var_def(lhs) {
+                    VarDef::Field(_) => {
+                        let this = frame
+                            .this
+                            .as_ref()
+                            .expect("semantic analysis forbids fields in the main method");
+                        this.borrow_mut().fields.insert(name.clone(), value.clone());
+                    }
+                    VarDef::Param(_) | VarDef::Local(_) => {
+                        frame.locals.insert(name.clone(), value.clone());
+                    }
+                    VarDef::Builtin(_) | VarDef::MainParam(_) => {
+                        unreachable!("semantic analysis forbids this assignment")
+                    }
+                }
+                Ok(value)
+            }
+            ast::Expr::FieldAccess(target, name) => {
+                let target = self.eval(frame, target)?;
+                let value = self.eval(frame, rhs)?;
+                match target {
+                    Value::Object(object) => {
+                        object
+                            .borrow_mut()
+                            .fields
+                            .insert(name.clone(), value.clone());
+                        Ok(value)
+                    }
+                    _ => Err(null_pointer(&lhs.span)),
+                }
+            }
+            ast::Expr::ArrayAccess(target, index) => {
+                let array = self.eval(frame, target)?;
+                let index = self.eval(frame, index)?.as_int();
+                let value = self.eval(frame, rhs)?;
+                let array = expect_array(array, &lhs.span)?;
+                let index = check_index(&array, index, &lhs.span)?;
+                array.borrow_mut()[index] = value.clone();
+                Ok(value)
+            }
+            _ => unreachable!("type checker guarantees an lvalue"),
+        }
+    }
+
+    fn eval_binary_op(
+        &self,
+        op: ast::BinaryOp,
+        lhs: &Value,
+        rhs: &Value,
+        span: &Span<'f>,
+    ) -> Eval<'f> {
+        use crate::ast::BinaryOp::*;
+        Ok(match op {
+            Equals => Value::Boolean(lhs.is_identical_to(rhs)),
+            NotEquals => Value::Boolean(!lhs.is_identical_to(rhs)),
+            LessThan => Value::Boolean(lhs.as_int() < rhs.as_int()),
+            GreaterThan => Value::Boolean(lhs.as_int() > rhs.as_int()),
+            LessEquals => Value::Boolean(lhs.as_int() <= rhs.as_int()),
+            GreaterEquals => Value::Boolean(lhs.as_int() >= rhs.as_int()),
+            Add => Value::Int(lhs.as_int().wrapping_add(rhs.as_int())),
+            Sub => Value::Int(lhs.as_int().wrapping_sub(rhs.as_int())),
+            Mul => Value::Int(lhs.as_int().wrapping_mul(rhs.as_int())),
+            Div | Mod => {
+                let (lhs, rhs) = (lhs.as_int(), rhs.as_int());
+                if rhs == 0 {
+                    return Err(Spanned {
+                        span: span.clone(),
+                        data: RuntimeError::DivisionByZero,
+                    });
+                }
+                // `wrapping_*` handles `i32::MIN / -1` like Java does
+                Value::Int(if op == Div {
+                    lhs.wrapping_div(rhs)
+                } else {
+                    lhs.wrapping_rem(rhs)
+                })
+            }
+            Assign | LogicalOr | LogicalAnd => unreachable!("evaluated in `eval`"),
+        })
+    }
+
+    fn eval_args(
+        &mut self,
+        frame: &mut Frame,
+        args: &'a Spanned<'f, ast::ArgumentList<'f>>,
+    ) -> Eval<'f, Vec<Value>> {
+        args.iter().map(|arg| self.eval(frame, arg)).collect()
+    }
+
+    /// Call the method `name` of class `class_name` on `this`, which is
+    /// `None` if the main method is called.
+    fn call_method(
+        &mut self,
+        class_name: &Symbol,
+        name: &str,
+        this: Option<ObjectRef>,
+        args: Vec<Value>,
+        span: &Span<'f>,
+    ) -> Eval<'f> {
+        let method = self
+            .program
+            .classes
+            .get(class_name)
+            .and_then(|class| class.method(name))
+            .expect("type checker guarantees the method exists");
+
+        if self.call_depth >= MAX_CALL_DEPTH {
+            return Err(Spanned {
+                span: span.clone(),
+                data: RuntimeError::StackOverflow {
+                    depth: MAX_CALL_DEPTH,
+                },
+            });
+        }
+
+        self.call_depth += 1;
+        let result = self.call(class_name, method, this, args);
+        self.call_depth -= 1;
+        result
+    }
+
+    fn call(
+        &mut self,
+        class_name: &Symbol,
+        method: &'a Spanned<'f, ast::ClassMember<'f>>,
+        this: Option<ObjectRef>,
+        args: Vec<Value>,
+    ) -> Eval<'f> {
+        let mut frame = Frame {
+            class_name: class_name.clone(),
+            this: None,
+            locals: HashMap::new(),
+        };
+
+        let body = match &method.kind {
+            ast::ClassMemberKind::Method(_, params, body) => {
+                frame.this = this;
+                let names = params.iter().map(|param| param.name.clone());
+                frame.locals.extend(names.zip(args));
+                body
+            }
+            // the parameter of the main method cannot be used, so it is not
+            // part of the frame
+            ast::ClassMemberKind::MainMethod(_, body) => body,
+            ast::ClassMemberKind::Field(_) => unreachable!("methods are not fields"),
+        };
+
+        match self.exec_block(&mut frame, body) {
+            Ok(()) => Ok(Value::Void),
+            Err(Interrupt::Return(value)) => Ok(value),
+            Err(Interrupt::Error(error)) => Err(error),
+        }
+    }
+
+    fn call_builtin(&mut self, method: BuiltinMethod, args: &[Value], span: &Span<'f>) -> Eval<'f> {
+        let result = match method {
+            BuiltinMethod::Println => {
+                writeln!(self.output, "{}", args[0].as_int()).map(|()| Value::Void)
+            }
+            // like Java, only the low-order eight bits are written
+            BuiltinMethod::Write => self
+                .output
+                .write_all(&[args[0].as_int() as u8])
+                .map(|()| Value::Void),
+            BuiltinMethod::Flush => self.output.flush().map(|()| Value::Void),
+            BuiltinMethod::Read => {
+                let mut byte = [0];
+                match self.input.read(&mut byte) {
+                    Ok(0) => Ok(Value::Int(-1)),
+                    Ok(_) => Ok(Value::Int(i32::from(byte[0]))),
+                    Err(err) => Err(err),
+                }
+            }
+        };
+        result.map_err(|err| io_error(span, &err))
+    }
+
+    fn var_def(&self, expr: &'a Spanned<'f, ast::Expr<'f>>) -> VarDef<'a, 'f> {
+        self.program
+            .names
+            .var_def(expr)
+            .expect("name resolution guarantees all variables are defined")
+    }
+}
+
+fn expect_array<'f>(value: Value, span: &Span<'f>) -> Eval<'f, ArrayRef> {
+    match value {
+        Value::Array(array) => Ok(array),
+        _ => Err(null_pointer(span)),
+    }
+}
+
+fn load_element<'f>(array: &ArrayRef, index: i32, span: &Span<'f>) -> Eval<'f> {
+    let index = check_index(array, index, span)?;
+    Ok(array.borrow()[index].clone())
+}
+
+fn check_index<'f>(array: &ArrayRef, index: i32, span: &Span<'f>) -> Eval<'f, usize> {
+    let length = array.borrow().len();
+    if index < 0 || index as usize >= length {
+        return Err(Spanned {
+            span: span.clone(),
+            data: RuntimeError::ArrayIndexOutOfBounds { index, length },
+        });
+    }
+    Ok(index as usize)
+}
+
+fn null_pointer<'f>(span: &Span<'f>) -> Spanned<'f, RuntimeError> {
+    Spanned {
+        span: span.clone(),
+        data: RuntimeError::NullPointerDereference,
+    }
+}
+
+fn io_error<'f>(span: &Span<'f>, err: &io::Error) -> Spanned<'f, RuntimeError> {
+    Spanned {
+        span: span.clone(),
+        data: RuntimeError::Io {
+            message: err.to_string(),
+        },
+    }
+}
@@ -0,0 +1,91 @@
+use crate::{ast, strtab::Symbol};
+use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+
+pub type ObjectRef = Rc<RefCell<Object>>;
+pub type ArrayRef = Rc<RefCell<Vec<Value>>>;
+
+/// A run-time value of the interpreter.
+///
+/// Objects and arrays are reference counted. Since MiniJava programs can
+/// build cyclic data structures, the memory of such structures is only
+/// reclaimed when the interpreter exits.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Int(i32),
+    Boolean(bool),
+    Null,
+    Object(ObjectRef),
+    Array(ArrayRef),
+    /// The result of calling a `void` method. The built-in objects like
+    /// `System.out` evaluate to `Void` as well, they have no run-time
+    /// representation.
+    Void,
+}
+
+#[derive(Debug)]
+pub struct Object {
+    pub class_name: Symbol,
+    pub fields: HashMap<Symbol, Value>,
+}
+
+impl Value {
+    /// The value of a field, array element or local variable of type `ty`
+    /// that has not been assigned yet.
+    pub fn default_for(ty: &ast::Type) -> Value {
+        match ty.basic {
+            _ if ty.array_depth > 0 => Value::Null,
+            ast::BasicType::Int => Value::Int(0),
+            ast::BasicType::Boolean => Value::Boolean(false),
+            ast::BasicType::Custom(_) => Value::Null,
+            ast::BasicType::Void => Value::Void,
+        }
+    }
+
+    pub fn new_object(class_name: Symbol, fields: HashMap<Symbol, Value>) -> Value {
+        Value::Object(Rc::new(RefCell::new(Object { class_name, fields })))
+    }
+
+    pub fn new_array(elements: Vec<Value>) -> Value {
+        Value::Array(Rc::new(RefCell::new(elements)))
+    }
+
+    /// Implements `==`. References are equal if they point to the same
+    /// object or array.
+    pub fn is_identical_to(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Null, Value::Null) => true,
+            (Value::Object(a), Value::Object(b)) => Rc::ptr_eq(a, b),
+            (Value::Array(a), Value::Array(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    pub fn as_int(&self) -> i32 {
+        match self {
+            Value::Int(value) => *value,
+            _ => panic!("type checker guarantees an int, found {}", self),
+        }
+    }
+
+    pub fn as_bool(&self) -> bool {
+        match self {
+            Value::Boolean(value) => *value,
+            _ => panic!("type checker guarantees a boolean, found {}", self),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Boolean(value) => write!(f, "{}", value),
+            Value::Null => write!(f, "null"),
+            Value::Object(object) => write!(f, "{} object", object.borrow().class_name),
+            Value::Array(array) => write!(f, "array of length {}", array.borrow().len()),
+            Value::Void => write!(f, "void"),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Export of the control flow graphs of the IR in the DOT format of
+//! Graphviz, e.g. to view them with `dot -Tpdf A.f.dot > A.f.pdf`.
+use super::{Instruction, Method, Program, Terminator};
+use failure::{Error, Fail, ResultExt};
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Fail)]
+pub enum DotError {
+    #[fail(display = "cannot create directory {:?}", path)]
+    CreateDirectory { path: PathBuf },
+    #[fail(display = "cannot write file {:?}", path)]
+    WriteFile { path: PathBuf },
+}
+
+/// Write one file `<class>.<method>.dot` per method of `program` to `dir`.
+pub fn dump(program: &Program, dir: &Path) -> Result<(), Error> {
+    fs::create_dir_all(dir).context(DotError::CreateDirectory {
+        path: dir.to_path_buf(),
+    })?;
+
+    for method in &program.methods {
+        let path = dir.join(format!("{}.{}.dot", method.class_name, method.name));
+        File::create(&path)
+            .and_then(|file| {
+                let mut out = BufWriter::new(file);
+                write_method(method, &mut out)?;
+                out.flush()
+            })
+            .context(DotError::WriteFile { path })?;
+    }
+
+    Ok(())
+}
+
+/// Write the control flow graph of `method`. Every block is labelled with
+/// its instructions, the edges of branches with their condition.
+pub fn write_method(method: &Method, out: &mut dyn Write) -> io::Result<()> {
+    writeln!(out, "digraph \"{}.{}\" {{", method.class_name, method.name)?;
+    writeln!(out, "    node [shape=box, fontname=\"monospace\"];")?;
+
+    for (id, block) in method.blocks.iter().enumerate() {
+        // `\l` ends a left-aligned line
+        let mut label = format!("b{}:\\l", id);
+        for &value in &block.instructions {
+            let instruction = method.value(value);
+            let line = match instruction {
+                // stores do not define a useful value
+                Instruction::StoreField { .. } | Instruction::StoreElement { .. } => {
+                    instruction.to_string()
+                }
+                _ => format!("{} = {}", value, instruction),
+            };
+            label.push_str(&escape(&line));
+            label.push_str("\\l");
+        }
+        label.push_str(&escape(&block.terminator.to_string()));
+        label.push_str("\\l");
+        writeln!(out, "    b{} [label=\"{}\"];", id, label)?;
+
+        match &block.terminator {
+            Terminator::Jump(target) => writeln!(out, "    b{} -> {};", id, target)?,
+            Terminator::Branch {
+                cond,
+                then_block,
+                else_block,
+            } => {
+                writeln!(out, "    b{} -> {} [label=\"{}\"];", id, then_block, cond)?;
+                writeln!(out, "    b{} -> {} [label=\"!{}\"];", id, else_block, cond)?;
+            }
+            Terminator::Return(_) | Terminator::Unreachable => (),
+        }
+    }
+
+    writeln!(out, "}}")
+}
+
+/// Escape text for a quoted DOT string.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{BinaryOp, Block, BlockId, Constant, ValueId, ValueKind};
+
+    #[test]
+    fn blocks_and_branches_are_labelled() {
+        let method = Method {
+            class_name: "A".into(),
+            name: "f".into(),
+            is_main: false,
+            params: vec![],
+            return_kind: ValueKind::Primitive,
+            values: vec![
+                Instruction::Const(Constant::Int(1)),
+                Instruction::Binary(BinaryOp::LessThan, ValueId(0), ValueId(0)),
+            ],
+            blocks: vec![
+                Block {
+                    instructions: vec![ValueId(0), ValueId(1)],
+                    terminator: Terminator::Branch {
+                        cond: ValueId(1),
+                        then_block: BlockId(1),
+                        else_block: BlockId(1),
+                    },
+                },
+                Block {
+                    instructions: vec![],
+                    terminator: Terminator::Return(Some(ValueId(0))),
+                },
+            ],
+        };
+
+        let mut out = Vec::new();
+        write_method(&method, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"digraph "A.f" {
+    node [shape=box, fontname="monospace"];
+    b0 [label="b0:\lv0 = 1\lv1 = v0 < v0\lbranch v1 b1 b1\l"];
+    b0 -> b1 [label="v1"];
+    b0 -> b1 [label="!v1"];
+    b1 [label="b1:\lreturn v0\l"];
+}
+"#
+        );
+    }
+}
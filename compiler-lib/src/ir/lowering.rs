@@ -0,0 +1,869 @@
+//! Lowering of the typed AST to the IR.
+//!
+//! The SSA form is constructed on the fly while the AST is lowered, using
+//! the algorithm of Braun et al., "Simple and Efficient Construction of
+//! Static Single Assignment Form" (CC 2013): the current value of every
+//! variable is tracked per block, and reading a variable in a block without
+//! a definition looks it up in the predecessors, inserting a `Phi` if there
+//! are several. Loop headers are only *sealed*, i.e. their predecessors are
+//! only complete, after the loop body was lowered, so their `Phi`s are
+//! completed at that point.
+//!
+//! The `Phi`s created this way are not minimal, e.g. a loop header gets a
+//! `Phi` for every variable read in the loop, even if the loop does not
+//! assign it. Such trivial `Phi`s are removed after a method is lowered.
+//!
+//! If `checks` is set, `null` checks are emitted for method calls and field
+//! and array accesses, except for references that are never `null`, like
+//! `this`. Like in Java, the target of a store and the arguments of a call
+//! are evaluated before the check.
+use super::{
+    BinaryOp, Block, BlockId, Class, Constant, Field, FieldRef, Instruction, Method, Program,
+    SourceLine, Terminator, UnaryOp, ValueId, ValueKind,
+};
+use crate::{
+    asciifile::{Span, Spanned},
+    ast,
+    sem::{CheckedType, TypedProgram, VarDef},
+    strtab::Symbol,
+    utils::RefEquality,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+pub fn lower(program: &TypedProgram<'_, '_>, checks: bool) -> Program {
+    let classes = program
+        .classes
+        .iter()
+        .map(|class| {
+            // only the first definition of a field counts, redefinitions
+            // are semantic errors
+            let fields = class
+                .decl
+                .members
+                .iter()
+                .filter(|member| {
+                    class
+                        .field(&member.name)
+                        .map_or(false, |field| std::ptr::eq(field, *member))
+                })
+                .map(|field| match &field.kind {
+                    ast::ClassMemberKind::Field(ty) => Field {
+                        name: field.name.clone(),
+                        kind: kind(&CheckedType::from(&**ty)),
+                    },
+                    _ => unreachable!("fields are declared by `ClassMemberKind::Field`"),
+                })
+                .collect();
+            let name = class.name().clone();
+            (name.clone(), Class { name, fields })
+        })
+        .collect();
+
+    let mut methods = Vec::new();
+    for decl in &program.program.classes {
+        for member in &decl.members {
+            match &member.kind {
+                ast::ClassMemberKind::Method(ty, params, body) => {
+                    let return_type = CheckedType::from(&**ty);
+                    let builder = MethodBuilder::new(
+                        program,
+                        checks,
+                        &decl.name,
+                        member,
+                        params,
+                        kind(&return_type),
+                    );
+                    methods.push(builder.build(body, return_type != CheckedType::Void));
+                }
+                ast::ClassMemberKind::MainMethod(_, body) => {
+                    let builder = MethodBuilder::new(
+                        program,
+                        checks,
+                        &decl.name,
+                        member,
+                        &[],
+                        ValueKind::Primitive,
+                    );
+                    methods.push(builder.build(body, false));
+                }
+                ast::ClassMemberKind::Field(_) => (),
+            }
+        }
+    }
+
+    Program { classes, methods }
+}
+
+/// A variable that may be assigned, i.e. everything that is not a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Variable<'a, 'f> {
+    Param(RefEquality<'a, Spanned<'f, ast::Parameter<'f>>>),
+    Local(RefEquality<'a, Spanned<'f, ast::Stmt<'f>>>),
+}
+
+struct MethodBuilder<'p, 'a, 'f> {
+    program: &'p TypedProgram<'a, 'f>,
+    /// Whether to emit `CheckNull` and `CheckBounds`
+    checks: bool,
+    method: Method,
+    /// The block instructions are appended to. `None` after a `return`,
+    /// statements are not lowered until control flow merges again, because
+    /// they are unreachable.
+    current: Option<BlockId>,
+    this: ValueId,
+    predecessors: Vec<Vec<BlockId>>,
+    sealed: HashSet<BlockId>,
+    definitions: HashMap<(Variable<'a, 'f>, BlockId), ValueId>,
+    incomplete_phis: HashMap<BlockId, Vec<(Variable<'a, 'f>, ValueId)>>,
+}
+
+impl<'p, 'a, 'f> MethodBuilder<'p, 'a, 'f> {
+    fn new(
+        program: &'p TypedProgram<'a, 'f>,
+        checks: bool,
+        class_name: &Symbol,
+        member: &'a Spanned<'f, ast::ClassMember<'f>>,
+        params: &'a [Spanned<'f, ast::Parameter<'f>>],
+        return_kind: ValueKind,
+    ) -> Self {
+        let is_main = match member.kind {
+            ast::ClassMemberKind::MainMethod(..) => true,
+            _ => false,
+        };
+        let mut builder = MethodBuilder {
+            program,
+            checks,
+            method: Method {
+                class_name: class_name.clone(),
+                name: member.name.clone(),
+                is_main,
+                // the `String[]` parameter of the main method is not part of
+                // `params`
+                params: if is_main {
+                    vec![ValueKind::Reference]
+                } else {
+                    params
+                        .iter()
+                        .map(|param| kind(&CheckedType::from(&*param.ty)))
+                        .collect()
+                },
+                return_kind,
+                values: Vec::new(),
+                blocks: Vec::new(),
+            },
+            current: None,
+            this: ValueId(0),
+            predecessors: Vec::new(),
+            sealed: HashSet::new(),
+            definitions: HashMap::new(),
+            incomplete_phis: HashMap::new(),
+        };
+
+        let entry = builder.new_block();
+        builder.seal(entry);
+        builder.current = Some(entry);
+        builder.this = builder.emit(Instruction::Param(0));
+        for (i, param) in params.iter().enumerate() {
+            let value = builder.emit(Instruction::Param(i + 1));
+            builder.write_variable(Variable::Param(RefEquality(param)), entry, value);
+        }
+        builder
+    }
+
+    fn build(mut self, body: &'a Spanned<'f, ast::Block<'f>>, returns_value: bool) -> Method {
+        self.lower_block(body);
+        if self.current.is_some() {
+            // the control flow analysis guarantees that non-void methods
+            // cannot reach the end of their body
+            self.terminate(if returns_value {
+                Terminator::Unreachable
+            } else {
+                Terminator::Return(None)
+            });
+        }
+        debug_assert!(self.incomplete_phis.is_empty());
+
+        remove_trivial_phis(&mut self.method);
+        self.method
+    }
+
+    fn lower_block(&mut self, block: &'a Spanned<'f, ast::Block<'f>>) {
+        for stmt in &block.statements {
+            if self.current.is_none() {
+                break;
+            }
+            self.lower_stmt(stmt);
+        }
+    }
+
+    fn lower_stmt(&mut self, stmt: &'a Spanned<'f, ast::Stmt<'f>>) {
+        use crate::ast::Stmt::*;
+        match &stmt.data {
+            Block(block) => self.lower_block(block),
+            Empty => (),
+            Error => unreachable!("programs with syntax errors are not lowered"),
+            If(cond, then_arm, else_arm) => {
+                let then_block = self.new_block();
+                let end_block = self.new_block();
+                let else_block = match else_arm {
+                    Some(_) => self.new_block(),
+                    None => end_block,
+                };
+                self.lower_cond(cond, then_block, else_block);
+                self.seal(then_block);
+
+                self.current = Some(then_block);
+                self.lower_stmt(then_arm);
+                self.jump(end_block);
+
+                if let Some(else_arm) = else_arm {
+                    self.seal(else_block);
+                    self.current = Some(else_block);
+                    self.lower_stmt(else_arm);
+                    self.jump(end_block);
+                }
+
+                self.seal(end_block);
+                // both arms might have returned
+                if !self.predecessors[end_block.0].is_empty() {
+                    self.current = Some(end_block);
+                }
+            }
+            While(cond, body) => {
+                let header = self.new_block();
+                let body_block = self.new_block();
+                let end_block = self.new_block();
+                self.jump(header);
+
+                self.current = Some(header);
+                self.lower_cond(cond, body_block, end_block);
+                self.seal(body_block);
+                self.seal(end_block);
+
+                self.current = Some(body_block);
+                self.lower_stmt(body);
+                self.jump(header);
+                // all predecessors of the header are known now
+                self.seal(header);
+
+                self.current = Some(end_block);
+            }
+            Expression(expr) => {
+                self.lower_expr(expr);
+            }
+            Return(expr) => {
+                let value = expr.as_ref().map(|expr| self.lower_expr(expr));
+                self.terminate(Terminator::Return(value));
+            }
+            LocalVariableDeclaration(ty, _, init) => {
+                let value = match init {
+                    Some(init) => self.lower_expr(init),
+                    None => self.emit(Instruction::Const(default_value(ty))),
+                };
+                let block = self.current_block();
+                self.write_variable(Variable::Local(RefEquality(stmt)), block, value);
+            }
+        }
+    }
+
+    /// Lower a condition to branches to `then_block` and `else_block`. Both
+    /// blocks must be sealed by the caller.
+    fn lower_cond(
+        &mut self,
+        cond: &'a Spanned<'f, ast::Expr<'f>>,
+        then_block: BlockId,
+        else_block: BlockId,
+    ) {
+        use crate::ast::{BinaryOp::*, Expr, UnaryOp};
+        match &cond.data {
+            Expr::Binary(LogicalAnd, lhs, rhs) => {
+                let rhs_block = self.new_block();
+                self.lower_cond(lhs, rhs_block, else_block);
+                self.seal(rhs_block);
+                self.current = Some(rhs_block);
+                self.lower_cond(rhs, then_block, else_block);
+            }
+            Expr::Binary(LogicalOr, lhs, rhs) => {
+                let rhs_block = self.new_block();
+                self.lower_cond(lhs, then_block, rhs_block);
+                self.seal(rhs_block);
+                self.current = Some(rhs_block);
+                self.lower_cond(rhs, then_block, else_block);
+            }
+            Expr::Unary(UnaryOp::Not, operand) => self.lower_cond(operand, else_block, then_block),
+            _ => {
+                let cond = self.lower_expr(cond);
+                self.terminate(Terminator::Branch {
+                    cond,
+                    then_block,
+                    else_block,
+                });
+            }
+        }
+    }
+
+    #[allow(clippy::cyclomatic_complexity)]
+    fn lower_expr(&mut self, expr: &'a Spanned<'f, ast::Expr<'f>>) -> ValueId {
+        use crate::ast::Expr::*;
+        match &expr.data {
+            Binary(ast::BinaryOp::Assign, lhs, rhs) => self.lower_assignment(lhs, rhs),
+            Binary(ast::BinaryOp::LogicalAnd, ..) | Binary(ast::BinaryOp::LogicalOr, ..) => {
+                let true_block = self.new_block();
+                let false_block = self.new_block();
+                let end_block = self.new_block();
+                self.lower_cond(expr, true_block, false_block);
+                self.seal(true_block);
+                self.seal(false_block);
+
+                self.current = Some(true_block);
+                let true_value = self.emit(Instruction::Const(Constant::Bool(true)));
+                self.jump(end_block);
+                self.current = Some(false_block);
+                let false_value = self.emit(Instruction::Const(Constant::Bool(false)));
+                self.jump(end_block);
+
+                self.seal(end_block);
+                self.current = Some(end_block);
+                self.emit(Instruction::Phi(vec![
+                    (true_block, true_value),
+                    (false_block, false_value),
+                ]))
+            }
+            Binary(op, lhs, rhs) => {
+                let lhs = self.lower_expr(lhs);
+                let rhs = self.lower_expr(rhs);
+                self.emit(Instruction::Binary(binary_op(*op), lhs, rhs))
+            }
+            Unary(op, operand) => {
+                let operand = self.lower_expr(operand);
+                let op = match op {
+                    ast::UnaryOp::Neg => UnaryOp::Neg,
+                    ast::UnaryOp::Not => UnaryOp::Not,
+                };
+                self.emit(Instruction::Unary(op, operand))
+            }
+            MethodInvocation(target, name, args) => match self.program.expr_type(target) {
+                CheckedType::Builtin(class) => {
+                    let method = class
+                        .method(name)
+                        .expect("type checker guarantees built-in methods exist");
+                    let args = self.lower_args(args);
+                    self.emit(Instruction::CallBuiltin(method, args))
+                }
+                CheckedType::Class(class_name) => {
+                    let receiver = self.lower_expr(target);
+                    self.lower_call(class_name.clone(), name, receiver, args, &expr.span)
+                }
+                ty => unreachable!("cannot call a method on {}", ty),
+            },
+            FieldAccess(target, name) => match self.program.expr_type(target) {
+                CheckedType::Class(class_name) => {
+                    let field = FieldRef {
+                        class_name: class_name.clone(),
+                        name: name.clone(),
+                    };
+                    let object = self.lower_expr(target);
+                    self.check_null(object, &expr.span);
+                    self.emit(Instruction::LoadField { object, field })
+                }
+                // built-in objects like `System.out` have no run-time
+                // representation
+                CheckedType::Builtin(_) => self.emit(Instruction::Const(Constant::Null)),
+                ty => unreachable!("cannot access a field of {}", ty),
+            },
+            ArrayAccess(target, index) => {
+                let array = self.lower_expr(target);
+                let index = self.lower_expr(index);
+                self.check_bounds(array, index, &expr.span);
+                let element = kind(self.program.expr_type(expr));
+                self.emit(Instruction::LoadElement {
+                    array,
+                    index,
+                    element,
+                })
+            }
+            Null => self.emit(Instruction::Const(Constant::Null)),
+            Boolean(value) => self.emit(Instruction::Const(Constant::Bool(*value))),
+            Int(_) => {
+                let value = self.program.int_value(expr);
+                self.emit(Instruction::Const(Constant::Int(value)))
+            }
+            Var(name) => match self.var_def(expr) {
+                VarDef::Field(_) => {
+                    let field = self.own_field(name);
+                    let object = self.this;
+                    self.emit(Instruction::LoadField { object, field })
+                }
+                VarDef::Param(param) => self.read_current(Variable::Param(RefEquality(param))),
+                VarDef::Local(stmt) => self.read_current(Variable::Local(RefEquality(stmt))),
+                VarDef::Builtin(_) => self.emit(Instruction::Const(Constant::Null)),
+                VarDef::MainParam(_) => unreachable!("the main parameter cannot be used"),
+            },
+            ThisMethodInvocation(name, args) => {
+                let class_name = self.method.class_name.clone();
+                let receiver = self.this;
+                self.lower_call(class_name, name, receiver, args, &expr.span)
+            }
+            This => self.this,
+            NewObject(class_name) => self.emit(Instruction::NewObject(class_name.clone())),
+            NewArray(basic, size, depth) => {
+                let size = self.lower_expr(size);
+                let element = CheckedType::from(&ast::Type {
+                    basic: basic.clone(),
+                    array_depth: *depth,
+                });
+                self.emit(Instruction::NewArray(size, kind(&element)))
+            }
+        }
+    }
+
+    /// Java evaluates the target of an assignment before its value.
+    fn lower_assignment(
+        &mut self,
+        lhs: &'a Spanned<'f, ast::Expr<'f>>,
+        rhs: &'a Spanned<'f, ast::Expr<'f>>,
+    ) -> ValueId {
+        match &lhs.data {
+            ast::Expr::Var(name) => {
+                let variable = match self.var_def(lhs) {
+                    VarDef::Field(_) => {
+                        let field = self.own_field(name);
+                        let value = self.lower_expr(rhs);
+                        let object = self.this;
+                        self.emit(Instruction::StoreField {
+                            object,
+                            field,
+                            value,
+                        });
+                        return value;
+                    }
+                    VarDef::Param(param) => Variable::Param(RefEquality(param)),
+                    VarDef::Local(stmt) => Variable::Local(RefEquality(stmt)),
+                    VarDef::Builtin(_) | VarDef::MainParam(_) => {
+                        unreachable!("semantic analysis forbids this assignment")
+                    }
+                };
+                let value = self.lower_expr(rhs);
+                let block = self.current_block();
+                self.write_variable(variable, block, value);
+                value
+            }
+            ast::Expr::FieldAccess(target, name) => {
+                let field = match self.program.expr_type(target) {
+                    CheckedType::Class(class_name) => FieldRef {
+                        class_name: class_name.clone(),
+                        name: name.clone(),
+                    },
+                    ty => unreachable!("cannot assign to a field of {}", ty),
+                };
+                let object = self.lower_expr(target);
+                let value = self.lower_expr(rhs);
+                self.check_null(object, &lhs.span);
+                self.emit(Instruction::StoreField {
+                    object,
+                    field,
+                    value,
+                });
+                value
+            }
+            ast::Expr::ArrayAccess(target, index) => {
+                let array = self.lower_expr(target);
+                let index = self.lower_expr(index);
+                let value = self.lower_expr(rhs);
+                self.check_bounds(array, index, &lhs.span);
+                self.emit(Instruction::StoreElement {
+                    array,
+                    index,
+                    value,
+                });
+                value
+            }
+            _ => unreachable!("type checker guarantees an lvalue"),
+        }
+    }
+
+    fn lower_call(
+        &mut self,
+        class_name: Symbol,
+        method_name: &Symbol,
+        receiver: ValueId,
+        args: &'a Spanned<'f, ast::ArgumentList<'f>>,
+        span: &Span<'f>,
+    ) -> ValueId {
+        let mut values = vec![receiver];
+        values.extend(self.lower_args(args));
+        self.check_null(receiver, span);
+        self.emit(Instruction::Call {
+            class_name,
+            method_name: method_name.clone(),
+            args: values,
+        })
+    }
+
+    fn lower_args(&mut self, args: &'a Spanned<'f, ast::ArgumentList<'f>>) -> Vec<ValueId> {
+        args.iter().map(|arg| self.lower_expr(arg)).collect()
+    }
+
+    /// Emit a `CheckNull` for `object`, unless it cannot be `null` because
+    /// it is `this` or a new object or array.
+    fn check_null(&mut self, object: ValueId, span: &Span<'f>) {
+        let may_be_null = match self.method.value(object) {
+            Instruction::Param(0) | Instruction::NewObject(_) | Instruction::NewArray(..) => false,
+            _ => true,
+        };
+        if self.checks && may_be_null {
+            let line = source_line(span);
+            self.emit(Instruction::CheckNull { object, line });
+        }
+    }
+
+    fn check_bounds(&mut self, array: ValueId, index: ValueId, span: &Span<'f>) {
+        self.check_null(array, span);
+        if self.checks {
+            let line = source_line(span);
+            self.emit(Instruction::CheckBounds { array, index, line });
+        }
+    }
+
+    fn own_field(&self, name: &Symbol) -> FieldRef {
+        FieldRef {
+            class_name: self.method.class_name.clone(),
+            name: name.clone(),
+        }
+    }
+
+    fn var_def(&self, expr: &'a Spanned<'f, ast::Expr<'f>>) -> VarDef<'a, 'f> {
+        self.program
+            .names
+            .var_def(expr)
+            .expect("name resolution guarantees all variables are defined")
+    }
+
+    fn current_block(&self) -> BlockId {
+        self.current
+            .expect("unreachable code is not lowered, so there is a current block")
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.method.blocks.push(Block::default());
+        self.predecessors.push(Vec::new());
+        BlockId(self.method.blocks.len() - 1)
+    }
+
+    /// Append an instruction to the current block.
+    fn emit(&mut self, instruction: Instruction) -> ValueId {
+        let block = self.current_block();
+        let value = self.new_value(instruction);
+        self.method.block_mut(block).instructions.push(value);
+        value
+    }
+
+    fn new_value(&mut self, instruction: Instruction) -> ValueId {
+        self.method.values.push(instruction);
+        ValueId(self.method.values.len() - 1)
+    }
+
+    /// End the current block. Does nothing if the current block already
+    /// ended, e.g. with a `return`.
+    fn terminate(&mut self, terminator: Terminator) {
+        if let Some(block) = self.current.take() {
+            for successor in terminator.successors() {
+                self.predecessors[successor.0].push(block);
+            }
+            self.method.block_mut(block).terminator = terminator;
+        }
+    }
+
+    fn jump(&mut self, target: BlockId) {
+        self.terminate(Terminator::Jump(target));
+    }
+
+    fn seal(&mut self, block: BlockId) {
+        for (variable, phi) in self.incomplete_phis.remove(&block).unwrap_or_default() {
+            self.add_phi_operands(variable, block, phi);
+        }
+        self.sealed.insert(block);
+    }
+
+    fn write_variable(&mut self, variable: Variable<'a, 'f>, block: BlockId, value: ValueId) {
+        self.definitions.insert((variable, block), value);
+    }
+
+    fn read_current(&mut self, variable: Variable<'a, 'f>) -> ValueId {
+        let block = self.current_block();
+        self.read_variable(variable, block)
+    }
+
+    fn read_variable(&mut self, variable: Variable<'a, 'f>, block: BlockId) -> ValueId {
+        if let Some(&value) = self.definitions.get(&(variable, block)) {
+            return value;
+        }
+
+        let value = if !self.sealed.contains(&block) {
+            let phi = self.new_phi(block);
+            self.incomplete_phis
+                .entry(block)
+                .or_default()
+                .push((variable, phi));
+            phi
+        } else if let [predecessor] = self.predecessors[block.0][..] {
+            self.read_variable(variable, predecessor)
+        } else {
+            // the phi breaks cycles in the recursive lookup
+            let phi = self.new_phi(block);
+            self.write_variable(variable, block, phi);
+            self.add_phi_operands(variable, block, phi);
+            phi
+        };
+        self.write_variable(variable, block, value);
+        value
+    }
+
+    fn new_phi(&mut self, block: BlockId) -> ValueId {
+        let phi = self.new_value(Instruction::Phi(Vec::new()));
+        self.method.block_mut(block).instructions.insert(0, phi);
+        phi
+    }
+
+    fn add_phi_operands(&mut self, variable: Variable<'a, 'f>, block: BlockId, phi: ValueId) {
+        assert!(
+            !self.predecessors[block.0].is_empty(),
+            "variables are defined before they are used"
+        );
+        for predecessor in self.predecessors[block.0].clone() {
+            let value = self.read_variable(variable, predecessor);
+            if let Instruction::Phi(operands) = self.method.value_mut(phi) {
+                operands.push((predecessor, value));
+            }
+        }
+    }
+}
+
+/// Remove `Phi`s whose operands are all the same value or the `Phi` itself,
+/// and replace their uses by that value. Removing a `Phi` may make other
+/// `Phi`s trivial, so this is repeated until nothing changes.
+fn remove_trivial_phis(method: &mut Method) {
+    let mut replacements = HashMap::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in &method.blocks {
+            for &value in &block.instructions {
+                let operands = match method.value(value) {
+                    Instruction::Phi(operands) if !replacements.contains_key(&value) => operands,
+                    _ => continue,
+                };
+                let mut unique = operands
+                    .iter()
+                    .map(|(_, operand)| resolve(&replacements, *operand))
+                    .filter(|&operand| operand != value);
+                if let Some(first) = unique.next() {
+                    if unique.all(|operand| operand == first) {
+                        replacements.insert(value, first);
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    method.replace_values(&replacements);
+}
+
+fn resolve(replacements: &HashMap<ValueId, ValueId>, mut value: ValueId) -> ValueId {
+    while let Some(&replacement) = replacements.get(&value) {
+        value = replacement;
+    }
+    value
+}
+
+fn default_value(ty: &ast::Type) -> Constant {
+    match ty.basic {
+        _ if ty.array_depth > 0 => Constant::Null,
+        ast::BasicType::Int => Constant::Int(0),
+        ast::BasicType::Boolean => Constant::Bool(false),
+        ast::BasicType::Custom(_) | ast::BasicType::Void => Constant::Null,
+    }
+}
+
+fn kind(ty: &CheckedType) -> ValueKind {
+    if ty.is_reference() {
+        ValueKind::Reference
+    } else {
+        ValueKind::Primitive
+    }
+}
+
+/// The line containing the start of `span`.
+fn source_line(span: &Span<'_>) -> SourceLine {
+    let start = span.start_position();
+    let line = start.get_line();
+    SourceLine {
+        number: start.line_number(),
+        text: Rc::from(line.as_str().trim_end()),
+    }
+}
+
+fn binary_op(op: ast::BinaryOp) -> BinaryOp {
+    use crate::ast::BinaryOp::*;
+    match op {
+        Add => BinaryOp::Add,
+        Sub => BinaryOp::Sub,
+        Mul => BinaryOp::Mul,
+        Div => BinaryOp::Div,
+        Mod => BinaryOp::Mod,
+        Equals => BinaryOp::Equals,
+        NotEquals => BinaryOp::NotEquals,
+        LessThan => BinaryOp::LessThan,
+        GreaterThan => BinaryOp::GreaterThan,
+        LessEquals => BinaryOp::LessEquals,
+        GreaterEquals => BinaryOp::GreaterEquals,
+        Assign | LogicalAnd | LogicalOr => unreachable!("lowered to control flow"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir;
+
+    fn phis_of_method(source: &str, name: &str) -> usize {
+        let program = ir::lower_source(source);
+        let method = program.method(name);
+        method
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instructions)
+            .filter(|&&value| method.value(value).is_phi())
+            .count()
+    }
+
+    #[test]
+    fn only_assigned_variables_get_phis() {
+        let source = r#"
+            class A {
+                public int f(int n, int step) {
+                    int sum = 0;
+                    while (n > 0) {
+                        sum = sum + step;
+                        n = n - 1;
+                    }
+                    return sum;
+                }
+                public static void main(String[] args) {}
+            }
+        "#;
+        // `sum` and `n`, but not `step`
+        assert_eq!(phis_of_method(source, "f"), 2);
+    }
+
+    #[test]
+    fn straight_line_code_has_no_phis() {
+        let source = r#"
+            class A {
+                public int f(int n) {
+                    int x = n;
+                    if (n > 0) {
+                        return x;
+                    }
+                    x = x + 1;
+                    return x;
+                }
+                public static void main(String[] args) {}
+            }
+        "#;
+        assert_eq!(phis_of_method(source, "f"), 0);
+    }
+
+    #[test]
+    fn references_that_are_never_null_are_not_checked() {
+        let source = r#"
+            class A {
+                public int x;
+                public int f(A a, int[] xs) {
+                    int sum = x + this.x + new A().x + new int[1][0];
+                    sum = sum + a.x;
+                    return sum + xs[a.g()] + g();
+                }
+                public int g() { return 0; }
+                public static void main(String[] args) {}
+            }
+        "#;
+        let program = ir::lower_source(source);
+        let method = program.method("f");
+        let checks: Vec<_> = method
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instructions)
+            .filter_map(|&value| match method.value(value) {
+                ir::Instruction::CheckNull { line, .. } => Some(("null", line.number)),
+                ir::Instruction::CheckBounds { line, .. } => Some(("bounds", line.number)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            checks,
+            vec![
+                ("bounds", 5),
+                ("null", 6),
+                ("null", 7),
+                ("null", 7),
+                ("bounds", 7)
+            ]
+        );
+    }
+
+    #[test]
+    fn values_know_whether_they_are_references() {
+        let source = r#"
+            class A {
+                public A next;
+                public A f(int n, A a) {
+                    while (n > 0) {
+                        a = a.next;
+                        n = n - 1;
+                    }
+                    return a;
+                }
+                public static void main(String[] args) {}
+            }
+        "#;
+        let program = ir::lower_source(source);
+        let method = program.method("f");
+        assert_eq!(
+            method.params,
+            vec![ir::ValueKind::Primitive, ir::ValueKind::Reference]
+        );
+        assert_eq!(method.return_kind, ir::ValueKind::Reference);
+
+        let kinds = program.value_kinds(method);
+        let mut phis: Vec<_> = method
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instructions)
+            .filter_map(|&value| match method.value(value) {
+                ir::Instruction::Phi(operands) => {
+                    let param =
+                        operands
+                            .iter()
+                            .find_map(|(_, operand)| match method.value(*operand) {
+                                ir::Instruction::Param(index) => Some(*index),
+                                _ => None,
+                            });
+                    Some((param, kinds[value.0]))
+                }
+                _ => None,
+            })
+            .collect();
+        phis.sort_by_key(|(param, _)| *param);
+        assert_eq!(
+            phis,
+            vec![
+                (Some(1), ir::ValueKind::Primitive),
+                (Some(2), ir::ValueKind::Reference)
+            ]
+        );
+    }
+}
@@ -0,0 +1,646 @@
+//! The intermediate representation between the typed AST and the backend.
+//!
+//! Every method is lowered to a control flow graph of basic blocks. The
+//! instructions are in SSA form: each instruction defines exactly one value,
+//! which is identified by a `ValueId`, and values are never reassigned.
+//! Local variables and parameters that are assigned in different branches
+//! or in loops are merged by `Phi` instructions at the start of a block.
+//!
+//! Objects and arrays are only accessed through explicit memory operations
+//! (`LoadField`, `StoreField`, `LoadElement`, `StoreElement`). Fields of the
+//! current object are accessed through the receiver, which is the value of
+//! `Param(0)`.
+//!
+//! Unless they are disabled, accesses through a reference that may be `null`
+//! and array accesses are preceded by `CheckNull` and `CheckBounds`
+//! instructions, which abort the program with an error message pointing at
+//! the source line of the access.
+//!
+//! `&&` and `||` do not exist in the IR. Their short-circuit evaluation is
+//! lowered to branches, and a `Phi` merges the result in value contexts.
+pub mod dot;
+mod lowering;
+
+pub use self::lowering::lower;
+
+use crate::{sem::BuiltinMethod, strtab::Symbol};
+use std::{collections::HashMap, fmt, rc::Rc};
+
+#[derive(Debug)]
+pub struct Program {
+    pub classes: HashMap<Symbol, Class>,
+    pub methods: Vec<Method>,
+}
+
+/// The fields of a class in declaration order.
+#[derive(Debug)]
+pub struct Class {
+    pub name: Symbol,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug)]
+pub struct Field {
+    pub name: Symbol,
+    pub kind: ValueKind,
+}
+
+impl Class {
+    pub fn field_index(&self, name: &str) -> usize {
+        self.fields
+            .iter()
+            .position(|field| &*field.name == name)
+            .expect("type checker guarantees fields exist")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ValueId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub usize);
+
+#[derive(Debug)]
+pub struct Method {
+    pub class_name: Symbol,
+    pub name: Symbol,
+    pub is_main: bool,
+    /// The kinds of the parameters, not counting the receiver
+    pub params: Vec<ValueKind>,
+    /// `Primitive` for methods that return nothing
+    pub return_kind: ValueKind,
+    /// All instructions of the method, indexed by the `ValueId` they define.
+    /// Instructions that were removed from their block remain here, so only
+    /// the instructions listed in `blocks` are part of the method.
+    pub values: Vec<Instruction>,
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, Default)]
+pub struct Block {
+    /// The instructions of the block in execution order. `Phi`s are always
+    /// at the start.
+    pub instructions: Vec<ValueId>,
+    pub terminator: Terminator,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    /// The parameter with the given index, `0` is the receiver
+    Param(usize),
+    Const(Constant),
+    Unary(UnaryOp, ValueId),
+    Binary(BinaryOp, ValueId, ValueId),
+    /// The value flowing in from the given predecessor
+    Phi(Vec<(BlockId, ValueId)>),
+    LoadField {
+        object: ValueId,
+        field: FieldRef,
+    },
+    StoreField {
+        object: ValueId,
+        field: FieldRef,
+        value: ValueId,
+    },
+    LoadElement {
+        array: ValueId,
+        index: ValueId,
+        element: ValueKind,
+    },
+    StoreElement {
+        array: ValueId,
+        index: ValueId,
+        value: ValueId,
+    },
+    NewObject(Symbol),
+    NewArray(ValueId, ValueKind),
+    /// A call of a method of `class_name`, `args[0]` is the receiver
+    Call {
+        class_name: Symbol,
+        method_name: Symbol,
+        args: Vec<ValueId>,
+    },
+    CallBuiltin(BuiltinMethod, Vec<ValueId>),
+    /// Abort the program if `object` is `null`
+    CheckNull {
+        object: ValueId,
+        line: SourceLine,
+    },
+    /// Abort the program if `index` is not a valid index of `array`, which
+    /// is not `null`
+    CheckBounds {
+        array: ValueId,
+        index: ValueId,
+        line: SourceLine,
+    },
+}
+
+/// Whether a value is a reference, which the garbage collector has to
+/// follow, or an `int` or boolean, which it skips.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    Primitive,
+    Reference,
+}
+
+/// The line of the source file containing the code that caused a run-time
+/// error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    /// Starting at `1`
+    pub number: usize,
+    pub text: Rc<str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Constant {
+    Int(i32),
+    Bool(bool),
+    Null,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldRef {
+    pub class_name: Symbol,
+    pub name: Symbol,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Equals,
+    NotEquals,
+    LessThan,
+    GreaterThan,
+    LessEquals,
+    GreaterEquals,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Terminator {
+    Jump(BlockId),
+    Branch {
+        cond: ValueId,
+        then_block: BlockId,
+        else_block: BlockId,
+    },
+    Return(Option<ValueId>),
+    /// The end of a block that is never reached, e.g. the end of a non-void
+    /// method ending in an infinite loop
+    Unreachable,
+}
+
+impl Default for Terminator {
+    fn default() -> Self {
+        Terminator::Unreachable
+    }
+}
+
+impl Method {
+    pub const ENTRY: BlockId = BlockId(0);
+
+    pub fn block(&self, id: BlockId) -> &Block {
+        &self.blocks[id.0]
+    }
+
+    pub fn block_mut(&mut self, id: BlockId) -> &mut Block {
+        &mut self.blocks[id.0]
+    }
+
+    pub fn value(&self, id: ValueId) -> &Instruction {
+        &self.values[id.0]
+    }
+
+    pub fn value_mut(&mut self, id: ValueId) -> &mut Instruction {
+        &mut self.values[id.0]
+    }
+
+    /// The predecessors of every block, indexed by `BlockId`.
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+        for (id, block) in self.blocks.iter().enumerate() {
+            for successor in block.terminator.successors() {
+                predecessors[successor.0].push(BlockId(id));
+            }
+        }
+        predecessors
+    }
+
+    /// The blocks reachable from the entry block in reverse postorder, i.e.
+    /// every block is listed before its successors, except along back edges.
+    pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut postorder = Vec::new();
+        // an explicit stack of (block, visited successors), deeply nested
+        // loops must not overflow the native stack
+        let mut stack = vec![(Method::ENTRY, 0)];
+        visited[Method::ENTRY.0] = true;
+        while let Some((block, next)) = stack.pop() {
+            let successors = self.block(block).terminator.successors();
+            match successors.get(next) {
+                Some(&successor) => {
+                    stack.push((block, next + 1));
+                    if !visited[successor.0] {
+                        visited[successor.0] = true;
+                        stack.push((successor, 0));
+                    }
+                }
+                None => postorder.push(block),
+            }
+        }
+        postorder.reverse();
+        postorder
+    }
+
+    /// Replace all uses of the keys of `replacements` by their values, and
+    /// remove the replaced instructions from their blocks. A value may be
+    /// replaced by a value that is replaced itself.
+    pub fn replace_values(&mut self, replacements: &HashMap<ValueId, ValueId>) {
+        if replacements.is_empty() {
+            return;
+        }
+
+        let resolve = |operand: &mut ValueId| {
+            while let Some(&replacement) = replacements.get(operand) {
+                *operand = replacement;
+            }
+        };
+        for instruction in &mut self.values {
+            instruction.for_each_operand_mut(resolve);
+        }
+        for block in &mut self.blocks {
+            block
+                .instructions
+                .retain(|value| !replacements.contains_key(value));
+            block.terminator.for_each_operand_mut(resolve);
+        }
+    }
+}
+
+impl Program {
+    /// The kind of every value of `method` that is part of a block, indexed
+    /// by `ValueId`. All other values are `Primitive`.
+    pub fn value_kinds(&self, method: &Method) -> Vec<ValueKind> {
+        use self::Instruction::*;
+        let values = || {
+            method
+                .blocks
+                .iter()
+                .flat_map(|block| &block.instructions)
+                .map(|&value| (value, method.value(value)))
+        };
+
+        let mut kinds = vec![ValueKind::Primitive; method.values.len()];
+        for (value, instruction) in values() {
+            kinds[value.0] = match instruction {
+                Param(0) => ValueKind::Reference,
+                Param(index) => method.params[index - 1],
+                Const(Constant::Null) | NewObject(_) | NewArray(..) => ValueKind::Reference,
+                LoadField { field, .. } => {
+                    let class = &self.classes[&field.class_name];
+                    class.fields[class.field_index(&field.name)].kind
+                }
+                LoadElement { element, .. } => *element,
+                Call {
+                    class_name,
+                    method_name,
+                    ..
+                } => {
+                    self.methods
+                        .iter()
+                        .find(|callee| {
+                            callee.class_name == *class_name && callee.name == *method_name
+                        })
+                        .expect("calls only refer to methods of the program")
+                        .return_kind
+                }
+                _ => ValueKind::Primitive,
+            };
+        }
+
+        // a `Phi` is a reference if one of its operands is, which may be a
+        // `Phi` that comes later in a loop
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (value, instruction) in values() {
+                if let Phi(operands) = instruction {
+                    let is_reference = operands
+                        .iter()
+                        .any(|(_, operand)| kinds[operand.0] == ValueKind::Reference);
+                    if is_reference && kinds[value.0] != ValueKind::Reference {
+                        kinds[value.0] = ValueKind::Reference;
+                        changed = true;
+                    }
+                }
+            }
+        }
+        kinds
+    }
+
+    #[cfg(test)]
+    pub fn method(&self, name: &str) -> &Method {
+        self.methods
+            .iter()
+            .find(|method| &*method.name == name)
+            .unwrap_or_else(|| panic!("no method named {}", name))
+    }
+
+    #[cfg(test)]
+    pub fn method_mut(&mut self, name: &str) -> &mut Method {
+        self.methods
+            .iter_mut()
+            .find(|method| &*method.name == name)
+            .unwrap_or_else(|| panic!("no method named {}", name))
+    }
+}
+
+impl Instruction {
+    pub fn operands(&self) -> Vec<ValueId> {
+        use self::Instruction::*;
+        match self {
+            Param(_) | Const(_) | NewObject(_) => vec![],
+            Unary(_, operand) | NewArray(operand, _) => vec![*operand],
+            LoadField { object, .. } | CheckNull { object, .. } => vec![*object],
+            Binary(_, lhs, rhs) => vec![*lhs, *rhs],
+            LoadElement { array, index, .. } | CheckBounds { array, index, .. } => {
+                vec![*array, *index]
+            }
+            StoreField { object, value, .. } => vec![*object, *value],
+            StoreElement {
+                array,
+                index,
+                value,
+            } => vec![*array, *index, *value],
+            Phi(operands) => operands.iter().map(|(_, operand)| *operand).collect(),
+            Call { args, .. } | CallBuiltin(_, args) => args.clone(),
+        }
+    }
+
+    pub fn for_each_operand_mut(&mut self, mut f: impl FnMut(&mut ValueId)) {
+        use self::Instruction::*;
+        match self {
+            Param(_) | Const(_) | NewObject(_) => (),
+            Unary(_, operand) | NewArray(operand, _) => f(operand),
+            LoadField { object, .. } | CheckNull { object, .. } => f(object),
+            Binary(_, lhs, rhs) => {
+                f(lhs);
+                f(rhs);
+            }
+            LoadElement { array, index, .. } | CheckBounds { array, index, .. } => {
+                f(array);
+                f(index);
+            }
+            StoreField { object, value, .. } => {
+                f(object);
+                f(value);
+            }
+            StoreElement {
+                array,
+                index,
+                value,
+            } => {
+                f(array);
+                f(index);
+                f(value);
+            }
+            Phi(operands) => operands.iter_mut().for_each(|(_, operand)| f(operand)),
+            Call { args, .. } | CallBuiltin(_, args) => args.iter_mut().for_each(f),
+        }
+    }
+
+    pub fn is_phi(&self) -> bool {
+        match self {
+            Instruction::Phi(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch {
+                then_block,
+                else_block,
+                ..
+            } => vec![*then_block, *else_block],
+            Terminator::Return(_) | Terminator::Unreachable => vec![],
+        }
+    }
+
+    pub fn operand(&self) -> Option<ValueId> {
+        match self {
+            Terminator::Branch { cond, .. } => Some(*cond),
+            Terminator::Return(value) => *value,
+            Terminator::Jump(_) | Terminator::Unreachable => None,
+        }
+    }
+
+    pub fn for_each_operand_mut(&mut self, mut f: impl FnMut(&mut ValueId)) {
+        match self {
+            Terminator::Branch { cond, .. } => f(cond),
+            Terminator::Return(Some(value)) => f(value),
+            Terminator::Jump(_) | Terminator::Return(None) | Terminator::Unreachable => (),
+        }
+    }
+}
+
+/// Lower a valid MiniJava program to the IR.
+#[cfg(test)]
+pub fn lower_source(source: &str) -> Program {
+    use crate::{
+        asciifile::AsciiFile,
+        ast,
+        context::Context,
+        lexer::{Lexer, TokenKind},
+        parser::Parser,
+        sem,
+        strtab::StringTable,
+    };
+
+    let strtab = StringTable::new();
+    let file = AsciiFile::new(source.as_bytes()).unwrap();
+    let context = Context::dummy(&file);
+    let tokens = Lexer::new(&strtab, &context)
+        .map(|token| token.unwrap())
+        .filter(|token| match token.data {
+            TokenKind::Whitespace | TokenKind::Comment(_) => false,
+            _ => true,
+        });
+    let program = match Parser::new(tokens).parse().unwrap() {
+        ast::AST::Program(program) => program,
+        ast::AST::Empty => panic!("empty program"),
+    };
+    let typed_program = sem::analyze(&program, &context);
+    assert!(!context.diagnostics.errored());
+    lower(&typed_program, true)
+}
+
+/// Lower a class `A` with the given members to the IR. An empty `main` is
+/// added unless the members declare one.
+#[cfg(test)]
+pub fn lower_method(members: &str) -> Program {
+    let main = if members.contains("static void main") {
+        ""
+    } else {
+        "public static void main(String[] args) {}"
+    };
+    lower_source(&format!("class A {{ {} {} }}", members, main))
+}
+
+impl fmt::Display for ValueId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}", self.0)
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "b{}", self.0)
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constant::Int(value) => write!(f, "{}", value),
+            Constant::Bool(value) => write!(f, "{}", value),
+            Constant::Null => write!(f, "null"),
+        }
+    }
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnaryOp::Neg => write!(f, "-"),
+            UnaryOp::Not => write!(f, "!"),
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::BinaryOp::*;
+        write!(
+            f,
+            "{}",
+            match self {
+                Add => "+",
+                Sub => "-",
+                Mul => "*",
+                Div => "/",
+                Mod => "%",
+                Equals => "==",
+                NotEquals => "!=",
+                LessThan => "<",
+                GreaterThan => ">",
+                LessEquals => "<=",
+                GreaterEquals => ">=",
+            }
+        )
+    }
+}
+
+/// Writes a comma separated list of values.
+struct ValueList<'v>(&'v [ValueId]);
+
+impl<'v> fmt::Display for ValueList<'v> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, value) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Instructions are written without the value they define, e.g. `v1 + v2`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::Instruction::*;
+        match self {
+            Param(index) => write!(f, "param {}", index),
+            Const(constant) => write!(f, "{}", constant),
+            Unary(op, operand) => write!(f, "{}{}", op, operand),
+            Binary(op, lhs, rhs) => write!(f, "{} {} {}", lhs, op, rhs),
+            Phi(operands) => {
+                write!(f, "phi [")?;
+                for (i, (block, value)) in operands.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", block, value)?;
+                }
+                write!(f, "]")
+            }
+            LoadField { object, field } => write!(f, "load {}.{}", object, field.name),
+            StoreField {
+                object,
+                field,
+                value,
+            } => write!(f, "store {}.{} = {}", object, field.name, value),
+            LoadElement { array, index, .. } => write!(f, "load {}[{}]", array, index),
+            StoreElement {
+                array,
+                index,
+                value,
+            } => write!(f, "store {}[{}] = {}", array, index, value),
+            NewObject(class_name) => write!(f, "new {}", class_name),
+            NewArray(length, ValueKind::Primitive) => write!(f, "new [{}]", length),
+            NewArray(length, ValueKind::Reference) => write!(f, "new ref [{}]", length),
+            Call {
+                class_name,
+                method_name,
+                args,
+            } => write!(
+                f,
+                "call {}.{}({})",
+                class_name,
+                method_name,
+                ValueList(args)
+            ),
+            CallBuiltin(method, args) => {
+                write!(f, "call builtin {}({})", method.name(), ValueList(args))
+            }
+            CheckNull { object, line } => {
+                write!(f, "check null {} (line {})", object, line.number)
+            }
+            CheckBounds { array, index, line } => write!(
+                f,
+                "check bounds {}[{}] (line {})",
+                array, index, line.number
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Terminator::Jump(target) => write!(f, "jump {}", target),
+            Terminator::Branch {
+                cond,
+                then_block,
+                else_block,
+            } => write!(f, "branch {} {} {}", cond, then_block, else_block),
+            Terminator::Return(Some(value)) => write!(f, "return {}", value),
+            Terminator::Return(None) => write!(f, "return"),
+            Terminator::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
@@ -2,6 +2,8 @@ use crate::{
     asciifile::{Position, PositionIterator, Span, Spanned},
     context::Context,
     diagnostics::u8_to_printable_representation,
+    error_codes::{Coded, ErrorCode},
+    lints::{self, Lint, Linted},
     strtab::*,
 };
 use failure::Fail;
@@ -72,6 +74,15 @@ impl fmt::Display for ErrorKind {
     }
 }
 
+impl Coded for ErrorKind {
+    fn code(&self) -> ErrorCode {
+        match self {
+            ErrorKind::UnclosedComment => ErrorCode(101),
+            ErrorKind::UnexpectedCharacter(_) => ErrorCode(102),
+        }
+    }
+}
+
 fn fmt_unexpected_character(f: &mut fmt::Formatter<'_>, byte: u8) -> fmt::Result {
     match byte as char {
         '\n' => write!(f, "Unexpected newline"),
@@ -102,6 +113,14 @@ pub enum Warning {
     CommentSeparatorInsideComment,
 }
 
+impl Linted for Warning {
+    fn lint(&self) -> &'static Lint {
+        match self {
+            Warning::CommentSeparatorInsideComment => &lints::COMMENT_SEPARATOR_INSIDE_COMMENT,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Keyword {
     Abstract,
@@ -159,68 +178,84 @@ pub enum Keyword {
     While,
 }
 
+impl Keyword {
+    pub fn as_str(self) -> &'static str {
+        use self::Keyword::*;
+        match self {
+            Abstract => "abstract",
+            Assert => "assert",
+            Boolean => "boolean",
+            Break => "break",
+            Byte => "byte",
+            Case => "case",
+            Catch => "catch",
+            Char => "char",
+            Class => "class",
+            Const => "const",
+            Continue => "continue",
+            Default => "default",
+            Double => "double",
+            Do => "do",
+            Else => "else",
+            Enum => "enum",
+            Extends => "extends",
+            False => "false",
+            Finally => "finally",
+            Final => "final",
+            Float => "float",
+            For => "for",
+            Goto => "goto",
+            If => "if",
+            Implements => "implements",
+            Import => "import",
+            InstanceOf => "instanceof",
+            Interface => "interface",
+            Int => "int",
+            Long => "long",
+            Native => "native",
+            New => "new",
+            Null => "null",
+            Package => "package",
+            Private => "private",
+            Protected => "protected",
+            Public => "public",
+            Return => "return",
+            Short => "short",
+            Static => "static",
+            StrictFp => "strictfp",
+            Super => "super",
+            Switch => "switch",
+            Synchronized => "synchronized",
+            This => "this",
+            Throws => "throws",
+            Throw => "throw",
+            Transient => "transient",
+            True => "true",
+            Try => "try",
+            Void => "void",
+            Volatile => "volatile",
+            While => "while",
+        }
+    }
+
+    /// Keywords that can start a statement. An identifier in their place is
+    /// likely one of them misspelled, e.g. `retrun x;`.
+    pub const STATEMENT_START: &'static [Keyword] = &[
+        Keyword::Return,
+        Keyword::If,
+        Keyword::While,
+        Keyword::Int,
+        Keyword::Boolean,
+        Keyword::Void,
+        Keyword::New,
+        Keyword::This,
+        Keyword::Null,
+    ];
+}
+
 impl fmt::Display for Keyword {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        use self::Keyword::*;
-        write!(
-            f,
-            "{}",
-            match self {
-                Abstract => "abstract",
-                Assert => "assert",
-                Boolean => "boolean",
-                Break => "break",
-                Byte => "byte",
-                Case => "case",
-                Catch => "catch",
-                Char => "char",
-                Class => "class",
-                Const => "const",
-                Continue => "continue",
-                Default => "default",
-                Double => "double",
-                Do => "do",
-                Else => "else",
-                Enum => "enum",
-                Extends => "extends",
-                False => "false",
-                Finally => "finally",
-                Final => "final",
-                Float => "float",
-                For => "for",
-                Goto => "goto",
-                If => "if",
-                Implements => "implements",
-                Import => "import",
-                InstanceOf => "instanceof",
-                Interface => "interface",
-                Int => "int",
-                Long => "long",
-                Native => "native",
-                New => "new",
-                Null => "null",
-                Package => "package",
-                Private => "private",
-                Protected => "protected",
-                Public => "public",
-                Return => "return",
-                Short => "short",
-                Static => "static",
-                StrictFp => "strictfp",
-                Super => "super",
-                Switch => "switch",
-                Synchronized => "synchronized",
-                This => "this",
-                Throws => "throws",
-                Throw => "throw",
-                Transient => "transient",
-                True => "true",
-                Try => "try",
-                Void => "void",
-                Volatile => "volatile",
-                While => "while",
-            }
-        )
+        write!(f, "{}", self.as_str())
     }
 }
 
@@ -434,6 +469,9 @@ impl<'f, 's> Lexer<'f, 's> {
                 '/' if self.input.matches("/*") => self.lex_comment(),
 
                 _ => self.lex_operator().unwrap_or_else(|| {
+                    // skip the character, so that lexing can continue after
+                    // the error
+                    self.input.next();
                     Err(LexicalError::new(
                         position.to_single_char_span(),
                         ErrorKind::UnexpectedCharacter(position.byte()),
@@ -486,7 +524,7 @@ impl<'f, 's> Lexer<'f, 's> {
 
         let comment_body = self.lex_while_multiple(2, |span, context| {
             if span.as_str() == "/*" {
-                context.diagnostics.warning(&Spanned {
+                context.diagnostics.lint(&Spanned {
                     span: span.clone(),
                     data: Warning::CommentSeparatorInsideComment,
                 });
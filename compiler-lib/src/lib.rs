@@ -17,14 +17,21 @@ pub mod asciifile;
 #[macro_use]
 mod utils;
 pub mod ast;
+pub mod backend;
 mod color;
 pub mod context;
 pub mod diagnostics;
+pub mod error_codes;
+pub mod interpreter;
+pub mod ir;
 pub mod lexer;
+pub mod lints;
+pub mod optimization;
 pub mod parser;
 #[macro_use]
 pub mod visitor;
 pub mod print;
 pub mod sem;
 mod spantracker;
+pub mod spelling;
 pub mod strtab;
@@ -0,0 +1,98 @@
+//! Lints are the kinds of warnings the compiler reports.
+//!
+//! The level of every lint decides whether its warnings are silently
+//! dropped (`allow`), reported as warnings (`warn`) or reported as errors
+//! (`deny`). Levels are set per lint by name, or for all lints at once with
+//! the name `warnings`, see `Diagnostics::set_lint_level`.
+use crate::{
+    asciifile::{MaybeSpanned, Spanned},
+    spelling::DidYouMean,
+};
+use failure::Fail;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Lint {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// The level of the lint unless it is set explicitly
+    pub default_level: LintLevel,
+}
+
+pub static COMMENT_SEPARATOR_INSIDE_COMMENT: Lint = Lint {
+    name: "comment_separator_inside_comment",
+    description: "`/*` inside of a comment, which suggests a nested comment",
+    default_level: LintLevel::Warn,
+};
+
+pub static UNREACHABLE_STATEMENT: Lint = Lint {
+    name: "unreachable_statement",
+    description: "statements that are never executed",
+    default_level: LintLevel::Warn,
+};
+
+/// All lints, ordered by name.
+pub static LINTS: &[&Lint] = &[&COMMENT_SEPARATOR_INSIDE_COMMENT, &UNREACHABLE_STATEMENT];
+
+/// The name that selects all lints.
+pub const ALL_WARNINGS: &str = "warnings";
+
+/// Warnings that belong to a lint.
+pub trait Linted {
+    fn lint(&self) -> &'static Lint;
+}
+
+impl<'f, T: Linted> Linted for Spanned<'f, T> {
+    fn lint(&self) -> &'static Lint {
+        self.data.lint()
+    }
+}
+
+impl<'f, T: Linted> Linted for MaybeSpanned<'f, T> {
+    fn lint(&self) -> &'static Lint {
+        (**self).lint()
+    }
+}
+
+/// The lints a level is set for, as given on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintSelector {
+    /// All lints, selected by `warnings`
+    All,
+    Lint(&'static Lint),
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "unknown lint '{}'{}", name, suggestion)]
+pub struct UnknownLint {
+    name: String,
+    suggestion: DidYouMean,
+}
+
+impl FromStr for LintSelector {
+    type Err = UnknownLint;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        if name == ALL_WARNINGS {
+            return Ok(LintSelector::All);
+        }
+        LINTS
+            .iter()
+            .find(|lint| lint.name == name)
+            .map(|lint| LintSelector::Lint(lint))
+            .ok_or_else(|| UnknownLint {
+                name: name.to_string(),
+                suggestion: DidYouMean::closest(
+                    name,
+                    LINTS.iter().map(|lint| lint.name).chain(Some(ALL_WARNINGS)),
+                ),
+            })
+    }
+}
@@ -0,0 +1,421 @@
+//! Constant folding and algebraic simplification.
+//!
+//! Instructions whose operands are constants are evaluated at compile time,
+//! with the wrap-around semantics of Java's `int`. A division or modulo by
+//! a constant zero is left alone, so that it still traps at run time. Some
+//! operations with a single constant operand, like `x * 1`, are simplified
+//! as well.
+//!
+//! Branches on a constant condition are replaced by jumps, which can make
+//! blocks unreachable. `Phi`s only consider the values flowing in from
+//! reachable predecessors, so constants propagate through them.
+//!
+//! `&&` and `||` are lowered to branches, so their constant operands are
+//! handled by folding these branches: `false && x` is `false` without
+//! evaluating `x`, and `true && x` is `x`. `x && true` leaves a `Phi` of
+//! `true` and `false` that depends on the branch on `x` only; such a `Phi` is
+//! replaced by `x` (or `!x`).
+use crate::ir::{BinaryOp, BlockId, Constant, Instruction, Method, Terminator, UnaryOp, ValueId};
+use std::collections::{HashMap, HashSet};
+
+pub fn run(method: &mut Method) {
+    // folding a branch can enable folding a `Phi`, which can enable folding
+    // another branch
+    while fold_once(method) {}
+}
+
+/// Returns whether anything changed.
+fn fold_once(method: &mut Method) -> bool {
+    let order = method.reverse_postorder();
+    let reachable: HashSet<BlockId> = order.iter().cloned().collect();
+    let predecessors = method.predecessors();
+    let mut replacements = HashMap::new();
+    let mut negations = Vec::new();
+    let mut changed = false;
+
+    for &block in &order {
+        for i in 0..method.block(block).instructions.len() {
+            let value = method.block(block).instructions[i];
+            let folded = match method.value(value) {
+                Instruction::Phi(operands) => {
+                    let operands: Vec<_> = operands
+                        .iter()
+                        .filter(|(predecessor, _)| reachable.contains(predecessor))
+                        .cloned()
+                        .collect();
+                    fold_phi(method, &predecessors, &reachable, block, value, &operands)
+                }
+                instruction => fold(method, instruction),
+            };
+
+            match folded {
+                Some(Folded::Constant(constant)) => {
+                    *method.value_mut(value) = Instruction::Const(constant);
+                    changed = true;
+                }
+                Some(Folded::Value(replacement)) => {
+                    replacements.insert(value, replacement);
+                    changed = true;
+                }
+                Some(Folded::Not(operand)) => {
+                    *method.value_mut(value) = Instruction::Unary(UnaryOp::Not, operand);
+                    negations.push((block, value));
+                    changed = true;
+                }
+                None => (),
+            }
+        }
+
+        changed |= fold_branch(method, block);
+    }
+
+    // changing the instructions of the blocks in the loop above would make
+    // the instruction indices invalid
+    for (block, value) in negations {
+        // a negated `Phi` must move behind the remaining `Phi`s
+        let instructions = &mut method.blocks[block.0].instructions;
+        instructions.retain(|&instruction| instruction != value);
+        let values = &method.values;
+        let position = instructions
+            .iter()
+            .position(|&instruction| !values[instruction.0].is_phi())
+            .unwrap_or_else(|| instructions.len());
+        instructions.insert(position, value);
+    }
+    method.replace_values(&replacements);
+    changed
+}
+
+/// The result of folding an instruction.
+#[derive(Debug, PartialEq, Eq)]
+enum Folded {
+    Constant(Constant),
+    /// The instruction computes an existing value.
+    Value(ValueId),
+    /// The instruction computes the negation of an existing boolean value.
+    Not(ValueId),
+}
+
+fn fold(method: &Method, instruction: &Instruction) -> Option<Folded> {
+    let constant = |value: ValueId| match method.value(value) {
+        Instruction::Const(constant) => Some(*constant),
+        _ => None,
+    };
+
+    match instruction {
+        Instruction::Unary(op, operand) => match (op, constant(*operand)) {
+            (UnaryOp::Neg, Some(Constant::Int(value))) => {
+                Some(Folded::Constant(Constant::Int(value.wrapping_neg())))
+            }
+            (UnaryOp::Not, Some(Constant::Bool(value))) => {
+                Some(Folded::Constant(Constant::Bool(!value)))
+            }
+            // `--x` and `!!x`
+            (_, None) => match method.value(*operand) {
+                Instruction::Unary(inner, inner_operand) if inner == op => {
+                    Some(Folded::Value(*inner_operand))
+                }
+                _ => None,
+            },
+            _ => None,
+        },
+        Instruction::Binary(op, lhs, rhs) => match (constant(*lhs), constant(*rhs)) {
+            (Some(lhs), Some(rhs)) => evaluate(*op, lhs, rhs).map(Folded::Constant),
+            (lhs_constant, rhs_constant) => simplify(*op, *lhs, lhs_constant, *rhs, rhs_constant),
+        },
+        _ => None,
+    }
+}
+
+/// Evaluate a binary operation on constants. Returns `None` for a division
+/// by zero.
+fn evaluate(op: BinaryOp, lhs: Constant, rhs: Constant) -> Option<Constant> {
+    use crate::ir::BinaryOp::*;
+    let result = match (op, lhs, rhs) {
+        (Equals, lhs, rhs) => Constant::Bool(lhs == rhs),
+        (NotEquals, lhs, rhs) => Constant::Bool(lhs != rhs),
+        (_, Constant::Int(lhs), Constant::Int(rhs)) => match op {
+            Add => Constant::Int(lhs.wrapping_add(rhs)),
+            Sub => Constant::Int(lhs.wrapping_sub(rhs)),
+            Mul => Constant::Int(lhs.wrapping_mul(rhs)),
+            Div | Mod if rhs == 0 => return None,
+            Div => Constant::Int(lhs.wrapping_div(rhs)),
+            Mod => Constant::Int(lhs.wrapping_rem(rhs)),
+            LessThan => Constant::Bool(lhs < rhs),
+            GreaterThan => Constant::Bool(lhs > rhs),
+            LessEquals => Constant::Bool(lhs <= rhs),
+            GreaterEquals => Constant::Bool(lhs >= rhs),
+            Equals | NotEquals => unreachable!(),
+        },
+        _ => unreachable!("type checker guarantees int operands"),
+    };
+    Some(result)
+}
+
+/// Algebraic identities for operations with at most one constant operand.
+fn simplify(
+    op: BinaryOp,
+    lhs: ValueId,
+    lhs_constant: Option<Constant>,
+    rhs: ValueId,
+    rhs_constant: Option<Constant>,
+) -> Option<Folded> {
+    use crate::ir::BinaryOp::*;
+    let int = |constant| match constant {
+        Some(Constant::Int(value)) => Some(value),
+        _ => None,
+    };
+
+    let folded = match (op, int(lhs_constant), int(rhs_constant)) {
+        (Add, _, Some(0)) | (Sub, _, Some(0)) | (Mul, _, Some(1)) | (Div, _, Some(1)) => {
+            Folded::Value(lhs)
+        }
+        (Add, Some(0), _) | (Mul, Some(1), _) => Folded::Value(rhs),
+        (Mul, _, Some(0)) | (Mul, Some(0), _) | (Mod, _, Some(1)) | (Mod, _, Some(-1)) => {
+            Folded::Constant(Constant::Int(0))
+        }
+        (Sub, None, None) if lhs == rhs => Folded::Constant(Constant::Int(0)),
+        (Equals, ..) | (LessEquals, ..) | (GreaterEquals, ..) if lhs == rhs => {
+            Folded::Constant(Constant::Bool(true))
+        }
+        (NotEquals, ..) | (LessThan, ..) | (GreaterThan, ..) if lhs == rhs => {
+            Folded::Constant(Constant::Bool(false))
+        }
+        // `x == true` and `x != false`
+        (Equals, ..) if rhs_constant == Some(Constant::Bool(true)) => Folded::Value(lhs),
+        (NotEquals, ..) if rhs_constant == Some(Constant::Bool(false)) => Folded::Value(lhs),
+        _ => return None,
+    };
+    Some(folded)
+}
+
+fn fold_phi(
+    method: &Method,
+    predecessors: &[Vec<BlockId>],
+    reachable: &HashSet<BlockId>,
+    block: BlockId,
+    phi: ValueId,
+    operands: &[(BlockId, ValueId)],
+) -> Option<Folded> {
+    let values: Vec<ValueId> = operands
+        .iter()
+        .map(|(_, value)| *value)
+        .filter(|&value| value != phi)
+        .collect();
+    let first = *values.first()?;
+    if values.iter().all(|&value| value == first) {
+        return Some(Folded::Value(first));
+    }
+
+    let constants: Vec<Constant> = values
+        .iter()
+        .filter_map(|&value| match method.value(value) {
+            Instruction::Const(constant) => Some(*constant),
+            _ => None,
+        })
+        .collect();
+    if constants.len() == values.len() && constants.iter().all(|&c| c == constants[0]) {
+        return Some(Folded::Constant(constants[0]));
+    }
+
+    fold_boolean_phi(method, predecessors, reachable, block, operands)
+}
+
+/// Fold a `Phi` of `true` and `false` that merges the two arms of a branch on
+/// `cond` to `cond` or `!cond`.
+fn fold_boolean_phi(
+    method: &Method,
+    predecessors: &[Vec<BlockId>],
+    reachable: &HashSet<BlockId>,
+    block: BlockId,
+    operands: &[(BlockId, ValueId)],
+) -> Option<Folded> {
+    let (first, second) = match operands {
+        [first, second] => (first, second),
+        _ => return None,
+    };
+    let origin =
+        |predecessor: BlockId| branch_origin(method, predecessors, reachable, predecessor, block);
+    let (branch, first_target) = origin(first.0)?;
+    let (other_branch, second_target) = origin(second.0)?;
+    if branch != other_branch {
+        return None;
+    }
+
+    let (cond, if_true, if_false) = match method.block(branch).terminator {
+        Terminator::Branch {
+            cond,
+            then_block,
+            else_block,
+        } => {
+            if (first_target, second_target) == (then_block, else_block) {
+                (cond, first.1, second.1)
+            } else if (first_target, second_target) == (else_block, then_block) {
+                (cond, second.1, first.1)
+            } else {
+                return None;
+            }
+        }
+        _ => return None,
+    };
+
+    let constant = |value: ValueId| match method.value(value) {
+        Instruction::Const(Constant::Bool(value)) => Some(*value),
+        _ => None,
+    };
+    match (constant(if_true)?, constant(if_false)?) {
+        (true, false) => Some(Folded::Value(cond)),
+        (false, true) => Some(Folded::Not(cond)),
+        _ => None,
+    }
+}
+
+/// Follow the edge `from -> to` backwards through blocks that have a single
+/// predecessor and end in a jump, until a branch is found. Returns the
+/// branching block and the successor of the branch that starts the chain.
+fn branch_origin(
+    method: &Method,
+    predecessors: &[Vec<BlockId>],
+    reachable: &HashSet<BlockId>,
+    mut from: BlockId,
+    mut to: BlockId,
+) -> Option<(BlockId, BlockId)> {
+    // a reachable cycle cannot consist of blocks with a single predecessor,
+    // the bound just keeps this from looping on malformed graphs
+    for _ in 0..method.blocks.len() {
+        match method.block(from).terminator {
+            Terminator::Branch { .. } => return Some((from, to)),
+            Terminator::Jump(_) => {
+                let mut reachable_predecessors = predecessors[from.0]
+                    .iter()
+                    .filter(|predecessor| reachable.contains(predecessor));
+                match (reachable_predecessors.next(), reachable_predecessors.next()) {
+                    (Some(&predecessor), None) => {
+                        to = from;
+                        from = predecessor;
+                    }
+                    _ => return None,
+                }
+            }
+            Terminator::Return(_) | Terminator::Unreachable => return None,
+        }
+    }
+    None
+}
+
+/// Replace a branch on a constant by a jump. Returns whether the branch was
+/// replaced.
+fn fold_branch(method: &mut Method, block: BlockId) -> bool {
+    let (taken, not_taken) = match method.block(block).terminator {
+        Terminator::Branch {
+            cond,
+            then_block,
+            else_block,
+        } => match method.value(cond) {
+            Instruction::Const(Constant::Bool(true)) => (then_block, else_block),
+            Instruction::Const(Constant::Bool(false)) => (else_block, then_block),
+            _ => return false,
+        },
+        _ => return false,
+    };
+
+    method.block_mut(block).terminator = Terminator::Jump(taken);
+    if taken != not_taken {
+        remove_phi_operands(method, not_taken, block);
+    }
+    true
+}
+
+/// Remove the incoming values of `predecessor` from the `Phi`s of `block`.
+fn remove_phi_operands(method: &mut Method, block: BlockId, predecessor: BlockId) {
+    for value in method.block(block).instructions.clone() {
+        if let Instruction::Phi(operands) = method.value_mut(value) {
+            operands.retain(|(operand_block, _)| *operand_block != predecessor);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir;
+
+    /// The returned value of a method ending in a single `return`.
+    fn returned_value(method: &str) -> Instruction {
+        let mut program = ir::lower_method(method);
+        let method = program.method_mut("f");
+        run(method);
+        let values: Vec<_> = method
+            .reverse_postorder()
+            .into_iter()
+            .filter_map(|block| match method.block(block).terminator {
+                Terminator::Return(Some(value)) => Some(value),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(values.len(), 1);
+        method.value(values[0]).clone()
+    }
+
+    #[test]
+    fn arithmetic_wraps_around() {
+        assert_eq!(
+            returned_value(
+                "public int f() { return 2147483647 + 1 + (-2147483648 / -1) * 2 - 7 % -2; }"
+            ),
+            Instruction::Const(Constant::Int(i32::max_value()))
+        );
+    }
+
+    #[test]
+    fn division_by_zero_is_not_folded() {
+        match returned_value("public int f() { return 1 / 0; }") {
+            Instruction::Binary(BinaryOp::Div, ..) => (),
+            instruction => panic!("folded to {:?}", instruction),
+        }
+    }
+
+    #[test]
+    fn constant_branches_are_removed() {
+        assert_eq!(
+            returned_value(
+                "public int f(int x) { int y = 1; if (x > 0 && 1 > 2) { y = x; } return y; }"
+            ),
+            Instruction::Const(Constant::Int(1))
+        );
+    }
+
+    #[test]
+    fn logical_operators_with_constant_operands_are_simplified() {
+        for expr in &["true && x", "x && true", "false || x", "x || false"] {
+            let method = format!("public boolean f(boolean x) {{ return {}; }}", expr);
+            assert_eq!(returned_value(&method), Instruction::Param(1), "{}", expr);
+        }
+        for expr in &["false && x", "x && false"] {
+            let method = format!("public boolean f(boolean x) {{ return {}; }}", expr);
+            assert_eq!(
+                returned_value(&method),
+                Instruction::Const(Constant::Bool(false)),
+                "{}",
+                expr
+            );
+        }
+
+        assert_eq!(
+            returned_value("public boolean f(boolean x) { return !x || false; }"),
+            Instruction::Unary(UnaryOp::Not, ValueId(1))
+        );
+    }
+
+    #[test]
+    fn algebraic_identities_are_simplified() {
+        assert_eq!(
+            returned_value("public int f(int x) { return (x + 0) * 1 - 0; }"),
+            Instruction::Param(1)
+        );
+        assert_eq!(
+            returned_value("public int f(int x) { return x * 0 + (x - x); }"),
+            Instruction::Const(Constant::Int(0))
+        );
+    }
+}
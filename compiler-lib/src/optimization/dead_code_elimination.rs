@@ -0,0 +1,188 @@
+//! Dead code elimination.
+//!
+//! Blocks that are unreachable from the entry block, e.g. because constant
+//! folding removed the branch leading to them, are removed from the method.
+//! Afterwards, instructions without side effects whose value is not used by
+//! any other live instruction are removed. Instructions that may trap at run
+//! time, like a division or a field access, are kept, because the trap is
+//! observable.
+use crate::ir::{BlockId, Constant, Instruction, Method, Terminator, ValueId};
+use std::collections::{HashMap, HashSet};
+
+pub fn run(method: &mut Method) {
+    remove_unreachable_blocks(method);
+    remove_dead_instructions(method);
+}
+
+/// Remove unreachable blocks and renumber the remaining blocks, keeping
+/// their order.
+fn remove_unreachable_blocks(method: &mut Method) {
+    let reachable: HashSet<BlockId> = method.reverse_postorder().into_iter().collect();
+    if reachable.len() == method.blocks.len() {
+        return;
+    }
+
+    let mut new_ids = HashMap::new();
+    let blocks = std::mem::replace(&mut method.blocks, Vec::new());
+    for (id, block) in blocks.into_iter().enumerate() {
+        if reachable.contains(&BlockId(id)) {
+            new_ids.insert(BlockId(id), BlockId(method.blocks.len()));
+            method.blocks.push(block);
+        }
+    }
+
+    for block in &mut method.blocks {
+        match &mut block.terminator {
+            Terminator::Jump(target) => *target = new_ids[target],
+            Terminator::Branch {
+                then_block,
+                else_block,
+                ..
+            } => {
+                *then_block = new_ids[then_block];
+                *else_block = new_ids[else_block];
+            }
+            Terminator::Return(_) | Terminator::Unreachable => (),
+        }
+
+        for &value in &block.instructions {
+            if let Instruction::Phi(operands) = &mut method.values[value.0] {
+                // values flowing in from unreachable blocks are never used
+                operands.retain(|(predecessor, _)| new_ids.contains_key(predecessor));
+                for (predecessor, _) in operands {
+                    *predecessor = new_ids[predecessor];
+                }
+            }
+        }
+    }
+}
+
+/// Remove instructions that have no side effects and whose values are not
+/// used, starting from the instructions that must be kept.
+fn remove_dead_instructions(method: &mut Method) {
+    let mut live = HashSet::new();
+    let mut worklist: Vec<ValueId> = Vec::new();
+    for block in &method.blocks {
+        for &value in &block.instructions {
+            if has_side_effects(method, method.value(value)) {
+                worklist.push(value);
+            }
+        }
+        worklist.extend(block.terminator.operand());
+    }
+
+    while let Some(value) = worklist.pop() {
+        if live.insert(value) {
+            worklist.extend(method.value(value).operands());
+        }
+    }
+
+    for block in &mut method.blocks {
+        block.instructions.retain(|value| live.contains(value));
+    }
+}
+
+/// Whether removing the instruction changes the behavior of the program,
+/// even if its value is not used.
+fn has_side_effects(method: &Method, instruction: &Instruction) -> bool {
+    use crate::ir::{BinaryOp::*, Instruction::*};
+    let int_constant = |value: ValueId| match method.value(value) {
+        Const(Constant::Int(constant)) => Some(*constant),
+        _ => None,
+    };
+
+    match instruction {
+        Param(_) | Const(_) | Unary(..) | Phi(_) | NewObject(_) => false,
+        // division by zero traps
+        Binary(Div, _, divisor) | Binary(Mod, _, divisor) => {
+            int_constant(*divisor).map_or(true, |divisor| divisor == 0)
+        }
+        Binary(..) => false,
+        // a negative size traps
+        NewArray(length, _) => int_constant(*length).map_or(true, |length| length < 0),
+        // accesses trap on `null` and indices out of bounds
+        LoadField { .. } | LoadElement { .. } => true,
+        StoreField { .. } | StoreElement { .. } | Call { .. } | CallBuiltin(..) => true,
+        // checks abort the program
+        CheckNull { .. } | CheckBounds { .. } => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ir::{self, Program},
+        optimization::constant_folding,
+    };
+
+    /// Fold constants and eliminate dead code in the method `f`, next to a
+    /// field `a`.
+    fn optimized_program(method: &str) -> Program {
+        let mut program = ir::lower_method(&format!("public int[] a; {}", method));
+        let method = program.method_mut("f");
+        constant_folding::run(method);
+        run(method);
+        program
+    }
+
+    fn instructions(method: &Method) -> Vec<&Instruction> {
+        method
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instructions)
+            .map(|&value| method.value(value))
+            .collect()
+    }
+
+    #[test]
+    fn unused_pure_instructions_are_removed() {
+        let program = optimized_program(
+            "public int f(int x, int y) { x + y * 2; x == y; new A(); int z = -x; return y; }",
+        );
+        let method = program.method("f");
+        assert_eq!(instructions(method), vec![&Instruction::Param(2)]);
+    }
+
+    #[test]
+    fn instructions_that_may_trap_are_kept() {
+        let program = optimized_program(
+            "public int f(int x) { x / 0; x % 2; a[x]; new int[x]; new int[1]; return 0; }",
+        );
+        let kept: Vec<_> = instructions(program.method("f"))
+            .into_iter()
+            .filter(|instruction| match instruction {
+                Instruction::Binary(..)
+                | Instruction::LoadElement { .. }
+                | Instruction::NewArray(..) => true,
+                _ => false,
+            })
+            .collect();
+        // `x % 2` and `new int[1]` cannot trap
+        assert_eq!(kept.len(), 3);
+    }
+
+    #[test]
+    fn unreachable_blocks_are_removed() {
+        let program = optimized_program(
+            "public int f(int x) { while (false) x = x + 1; if (true) return x; return 1; }",
+        );
+        let method = program.method("f");
+        assert_eq!(method.blocks.len(), method.reverse_postorder().len());
+        let returns = method
+            .blocks
+            .iter()
+            .filter(|block| match block.terminator {
+                Terminator::Return(_) => true,
+                _ => false,
+            })
+            .count();
+        assert_eq!(returns, 1);
+        assert!(instructions(method)
+            .iter()
+            .all(|instruction| match instruction {
+                Instruction::Binary(..) | Instruction::Phi(_) => false,
+                _ => true,
+            }));
+    }
+}
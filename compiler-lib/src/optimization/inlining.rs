@@ -0,0 +1,358 @@
+//! Method inlining.
+//!
+//! MiniJava has no inheritance, so the target of every `Call` is known at
+//! compile time. A call is replaced by a copy of the body of the called
+//! method if the method is small, i.e. has at most `threshold` instructions,
+//! or if it is called from a single place in the program. Methods that are
+//! part of a cycle in the call graph are never inlined.
+//!
+//! The callers are processed bottom-up in the call graph, so the methods
+//! they call already had their own calls inlined. Methods that are no longer
+//! called after inlining are removed from the program.
+use crate::{
+    ir::{Block, BlockId, Instruction, Method, Program, Terminator, ValueId},
+    strtab::Symbol,
+};
+use std::collections::{HashMap, HashSet};
+
+pub fn run(program: &mut Program, threshold: usize) {
+    let indices: HashMap<(Symbol, Symbol), usize> = program
+        .methods
+        .iter()
+        .enumerate()
+        .map(|(index, method)| ((method.class_name.clone(), method.name.clone()), index))
+        .collect();
+    let callees: Vec<Vec<usize>> = program
+        .methods
+        .iter()
+        .map(|method| {
+            calls(method)
+                .into_iter()
+                .map(|(_, _, call)| indices[&call_target(method.value(call))])
+                .collect()
+        })
+        .collect();
+
+    let mut call_sites = vec![0; program.methods.len()];
+    for &callee in callees.iter().flatten() {
+        call_sites[callee] += 1;
+    }
+    let recursive = recursive_methods(&callees);
+
+    for caller in bottom_up_order(&callees) {
+        // the callees were processed before, so the inlined bodies only
+        // contain calls that are not inlined
+        while let Some((block, position, callee)) = calls(&program.methods[caller])
+            .into_iter()
+            .find_map(|(block, position, call)| {
+                let callee = indices[&call_target(program.methods[caller].value(call))];
+                let inline = !recursive.contains(&callee)
+                    && !program.methods[callee].is_main
+                    && (size(&program.methods[callee]) <= threshold || call_sites[callee] == 1);
+                if inline {
+                    Some((block, position, callee))
+                } else {
+                    None
+                }
+            })
+        {
+            // recursive methods are never inlined, so caller and callee
+            // are different methods
+            let (caller, callee) = if caller < callee {
+                let (head, tail) = program.methods.split_at_mut(callee);
+                (&mut head[caller], &tail[0])
+            } else {
+                let (head, tail) = program.methods.split_at_mut(caller);
+                (&mut tail[0], &head[callee])
+            };
+            inline_call(caller, block, position, callee);
+        }
+    }
+
+    remove_uncalled_methods(program);
+}
+
+/// The number of instructions of the method, not counting parameters.
+fn size(method: &Method) -> usize {
+    method
+        .blocks
+        .iter()
+        .flat_map(|block| &block.instructions)
+        .filter(|&&value| match method.value(value) {
+            Instruction::Param(_) => false,
+            _ => true,
+        })
+        .count()
+}
+
+/// The block, the position in the block and the value of every `Call` in
+/// the method.
+fn calls(method: &Method) -> Vec<(BlockId, usize, ValueId)> {
+    let mut calls = Vec::new();
+    for (id, block) in method.blocks.iter().enumerate() {
+        for (position, &value) in block.instructions.iter().enumerate() {
+            if let Instruction::Call { .. } = method.value(value) {
+                calls.push((BlockId(id), position, value));
+            }
+        }
+    }
+    calls
+}
+
+fn call_target(call: &Instruction) -> (Symbol, Symbol) {
+    match call {
+        Instruction::Call {
+            class_name,
+            method_name,
+            ..
+        } => (class_name.clone(), method_name.clone()),
+        _ => unreachable!("not a call"),
+    }
+}
+
+/// The methods that can reach themselves in the call graph.
+fn recursive_methods(callees: &[Vec<usize>]) -> HashSet<usize> {
+    (0..callees.len())
+        .filter(|&method| {
+            let mut visited = HashSet::new();
+            let mut stack = callees[method].clone();
+            while let Some(callee) = stack.pop() {
+                if callee == method {
+                    return true;
+                }
+                if visited.insert(callee) {
+                    stack.extend(&callees[callee]);
+                }
+            }
+            false
+        })
+        .collect()
+}
+
+/// All methods in postorder of the call graph, i.e. every method is listed
+/// after the methods it calls, except for calls within a cycle.
+fn bottom_up_order(callees: &[Vec<usize>]) -> Vec<usize> {
+    let mut visited = vec![false; callees.len()];
+    let mut order = Vec::new();
+    for root in 0..callees.len() {
+        if visited[root] {
+            continue;
+        }
+        visited[root] = true;
+        let mut stack = vec![(root, 0)];
+        while let Some((method, next)) = stack.pop() {
+            match callees[method].get(next) {
+                Some(&callee) => {
+                    stack.push((method, next + 1));
+                    if !visited[callee] {
+                        visited[callee] = true;
+                        stack.push((callee, 0));
+                    }
+                }
+                None => order.push(method),
+            }
+        }
+    }
+    order
+}
+
+/// Replace the `Call` at `position` in `block` by the body of `callee`.
+///
+/// The block is split after the call. The call jumps to a copy of the
+/// blocks of the callee, in which the parameters are replaced by the
+/// arguments and every return jumps to the continuation. The returned
+/// values are merged by a `Phi` that takes the place of the call.
+fn inline_call(caller: &mut Method, block: BlockId, position: usize, callee: &Method) {
+    let call = caller.block(block).instructions[position];
+    let args = match caller.value(call) {
+        Instruction::Call { args, .. } => args.clone(),
+        _ => unreachable!("not a call"),
+    };
+
+    let continuation = BlockId(caller.blocks.len());
+    let block_offset = continuation.0 + 1;
+    let value_offset = caller.values.len();
+    let map_block = |block: BlockId| BlockId(block.0 + block_offset);
+    let map_value = |value: ValueId| match callee.value(value) {
+        Instruction::Param(index) => args[*index],
+        _ => ValueId(value.0 + value_offset),
+    };
+
+    let instructions = caller.block_mut(block).instructions.split_off(position + 1);
+    caller.block_mut(block).instructions.pop();
+    // the entry block of a method has no predecessors, so it needs no `Phi`s
+    let terminator = std::mem::replace(
+        &mut caller.block_mut(block).terminator,
+        Terminator::Jump(map_block(Method::ENTRY)),
+    );
+    for successor in terminator.successors() {
+        for &value in &caller.blocks[successor.0].instructions {
+            if let Instruction::Phi(operands) = &mut caller.values[value.0] {
+                for (predecessor, _) in operands {
+                    if *predecessor == block {
+                        *predecessor = continuation;
+                    }
+                }
+            }
+        }
+    }
+    caller.blocks.push(Block {
+        instructions,
+        terminator,
+    });
+
+    // the parameters are copied as well to keep the offset of the values,
+    // but they are not part of any block
+    for instruction in &callee.values {
+        let mut instruction = instruction.clone();
+        instruction.for_each_operand_mut(|operand| *operand = map_value(*operand));
+        if let Instruction::Phi(operands) = &mut instruction {
+            for (predecessor, _) in operands {
+                *predecessor = map_block(*predecessor);
+            }
+        }
+        caller.values.push(instruction);
+    }
+
+    let mut returned = Vec::new();
+    for (id, callee_block) in callee.blocks.iter().enumerate() {
+        let instructions = callee_block
+            .instructions
+            .iter()
+            .filter(|&&value| match callee.value(value) {
+                Instruction::Param(_) => false,
+                _ => true,
+            })
+            .map(|value| ValueId(value.0 + value_offset))
+            .collect();
+        let terminator = match &callee_block.terminator {
+            Terminator::Jump(target) => Terminator::Jump(map_block(*target)),
+            Terminator::Branch {
+                cond,
+                then_block,
+                else_block,
+            } => Terminator::Branch {
+                cond: map_value(*cond),
+                then_block: map_block(*then_block),
+                else_block: map_block(*else_block),
+            },
+            Terminator::Return(value) => {
+                if let Some(value) = value {
+                    returned.push((map_block(BlockId(id)), map_value(*value)));
+                }
+                Terminator::Jump(continuation)
+            }
+            Terminator::Unreachable => Terminator::Unreachable,
+        };
+        caller.blocks.push(Block {
+            instructions,
+            terminator,
+        });
+    }
+
+    match returned.len() {
+        // a void method, or the continuation is unreachable
+        0 => (),
+        1 => {
+            let mut replacements = HashMap::new();
+            replacements.insert(call, returned[0].1);
+            caller.replace_values(&replacements);
+        }
+        _ => {
+            *caller.value_mut(call) = Instruction::Phi(returned);
+            caller.block_mut(continuation).instructions.insert(0, call);
+        }
+    }
+}
+
+fn remove_uncalled_methods(program: &mut Program) {
+    let called: HashSet<(Symbol, Symbol)> = program
+        .methods
+        .iter()
+        .flat_map(|method| {
+            calls(method)
+                .into_iter()
+                .map(move |(_, _, call)| call_target(method.value(call)))
+        })
+        .collect();
+    program.methods.retain(|method| {
+        method.is_main || called.contains(&(method.class_name.clone(), method.name.clone()))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir;
+
+    fn inlined_program(methods: &str, threshold: usize) -> Program {
+        let mut program = ir::lower_method(&format!(
+            "public int x; {} public static void main(String[] args) {{ \
+             A a = new A(); a.x = 1; System.out.println(a.f(2)); }}",
+            methods
+        ));
+        run(&mut program, threshold);
+        program
+    }
+
+    fn called_methods(method: &Method) -> Vec<String> {
+        calls(method)
+            .into_iter()
+            .map(|(_, _, call)| call_target(method.value(call)).1.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn small_methods_are_inlined() {
+        let program = inlined_program(
+            "public int f(int y) { return get() + get() + y; } \
+             public int get() { return x; }",
+            1,
+        );
+        assert!(called_methods(program.method("main")).is_empty());
+        assert_eq!(program.methods.len(), 1);
+    }
+
+    #[test]
+    fn methods_called_once_are_inlined_regardless_of_size() {
+        let program = inlined_program(
+            "public int f(int y) { \
+             while (y < 10) { if (y % 2 == 0) y = y + 3; else y = y * 2; } \
+             if (y > 15) return y; return x; }",
+            0,
+        );
+        assert!(called_methods(program.method("main")).is_empty());
+        let main = program.method("main");
+        let phis = main
+            .blocks
+            .iter()
+            .flat_map(|block| &block.instructions)
+            .filter(|&&value| main.value(value).is_phi())
+            .count();
+        // the loop variable, the `if` in the loop and the two returns
+        assert_eq!(phis, 3);
+    }
+
+    #[test]
+    fn large_methods_called_more_than_once_are_not_inlined() {
+        let program = inlined_program(
+            "public int f(int y) { return g(y) + g(y + 1); } \
+             public int g(int y) { return y * y + y * 2 + x; }",
+            3,
+        );
+        // `f` is called once, so `main` calls `g` now
+        assert_eq!(called_methods(program.method("main")), vec!["g", "g"]);
+        assert_eq!(program.methods.len(), 2);
+    }
+
+    #[test]
+    fn recursive_methods_are_not_inlined() {
+        let program = inlined_program(
+            "public int f(int y) { if (y == 0) return 0; return g(y - 1); } \
+             public int g(int y) { return f(y); }",
+            100,
+        );
+        assert_eq!(called_methods(program.method("main")), vec!["f"]);
+        assert_eq!(program.methods.len(), 3);
+    }
+}
@@ -0,0 +1,27 @@
+//! Optimizations on the IR.
+//!
+//! The passes transform the methods of an `ir::Program` in place. They run
+//! after the program was lowered and before the backend generates code.
+//! Inlining runs first, so that the other passes can optimize the inlined
+//! methods together with their callers.
+mod constant_folding;
+mod dead_code_elimination;
+mod inlining;
+
+use crate::ir::Program;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Methods with at most this many instructions are inlined. Methods
+    /// that are called from a single place are inlined regardless of their
+    /// size.
+    pub inline_threshold: usize,
+}
+
+pub fn optimize(program: &mut Program, options: &Options) {
+    inlining::run(program, options.inline_threshold);
+    for method in &mut program.methods {
+        constant_folding::run(method);
+        dead_code_elimination::run(method);
+    }
+}
@@ -4,8 +4,11 @@ use crate::{
         Span, Spanned,
     },
     ast,
+    diagnostics::Diagnostics,
+    error_codes::{Coded, ErrorCode},
     lexer::{Keyword, Operator, Token, TokenKind},
     spantracker::*,
+    spelling::{closest_match, DidYouMean},
     strtab::Symbol,
 };
 
@@ -43,18 +46,60 @@ const BINARY_OPERATORS: &[(Operator, ast::BinaryOp, Precedence, Assoc)] = &[
 
 ];
 
-#[rustfmt::skip]
 #[derive(Debug, Clone, Fail)]
 pub enum SyntaxError {
-    #[fail(display = "expected {}, found {}", expected, actual)]
     UnexpectedToken {
         actual: String,
-        expected: String, // TODO This is temporary, shouldn't be string
+        expected: ExpectedTokens,
+        suggestion: DidYouMean,
     },
-    #[fail(display = "unexpected end of file")]
     UnexpectedEOF,
 }
 
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyntaxError::UnexpectedToken {
+                actual,
+                expected,
+                suggestion,
+            } if expected.0.is_empty() => write!(f, "unexpected {}{}", actual, suggestion),
+            SyntaxError::UnexpectedToken {
+                actual,
+                expected,
+                suggestion,
+            } => write!(f, "expected {}, found {}{}", expected, actual, suggestion),
+            SyntaxError::UnexpectedEOF => write!(f, "unexpected end of file"),
+        }
+    }
+}
+
+impl Coded for SyntaxError {
+    fn code(&self) -> ErrorCode {
+        match self {
+            SyntaxError::UnexpectedToken { .. } => ErrorCode(201),
+            SyntaxError::UnexpectedEOF => ErrorCode(202),
+        }
+    }
+}
+
+/// The descriptions of all alternatives the parser tried at the position of
+/// an unexpected token, in the order they were tried.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedTokens(pub Vec<String>);
+
+/// Written as `a` for a single alternative and `one of a, b or c` for
+/// several ones.
+impl fmt::Display for ExpectedTokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.split_last() {
+            None => Ok(()),
+            Some((last, [])) => write!(f, "{}", last),
+            Some((last, others)) => write!(f, "one of {} or {}", others.join(", "), last),
+        }
+    }
+}
+
 impl<'f> From<EOF> for MaybeSpanned<'f, SyntaxError> {
     fn from(_: EOF) -> Self {
         MaybeSpanned::WithoutSpan(SyntaxError::UnexpectedEOF)
@@ -68,6 +113,12 @@ pub trait ExpectedToken: fmt::Debug + fmt::Display {
     fn matches(&self, token: &TokenKind) -> bool {
         self.matching(token).is_some()
     }
+
+    /// The keyword this matches, if any. Used to suggest keywords for
+    /// misspelled identifiers.
+    fn keyword(&self) -> Option<Keyword> {
+        None
+    }
 }
 
 #[derive(Debug, Clone, Display)]
@@ -118,6 +169,13 @@ impl ExpectedToken for Exactly {
             None
         }
     }
+
+    fn keyword(&self) -> Option<Keyword> {
+        match self.0 {
+            TokenKind::Keyword(keyword) => Some(keyword),
+            _ => None,
+        }
+    }
 }
 
 impl ExpectedToken for BinaryOp {
@@ -174,6 +232,14 @@ where
     I: Iterator<Item = Token<'f>>,
 {
     lexer: SpanTracker<'f, I>,
+    errors: Vec<MaybeSpanned<'f, SyntaxError>>,
+    diagnostics: Option<&'f Diagnostics>,
+    /// Everything that was tried at `expected_span`, see `expect`
+    expected: Vec<String>,
+    expected_keywords: Vec<Keyword>,
+    expected_span: Option<Span<'f>>,
+    /// The identifier the statement being parsed starts with, if any
+    statement_start: Option<Symbol>,
 }
 
 macro_rules! spanned {
@@ -210,6 +276,12 @@ where
     pub fn new(lexer: I) -> Self {
         Parser {
             lexer: SpanTracker::new(lexer),
+            errors: Vec::new(),
+            diagnostics: None,
+            expected: Vec::new(),
+            expected_keywords: Vec::new(),
+            expected_span: None,
+            statement_start: None,
         }
     }
 
@@ -218,20 +290,82 @@ where
     where
         E: ExpectedToken,
     {
-        let actual = self.lexer.next()?;
-
-        want.matching(&actual.data)
-            .map(|yielded| actual.map(|_| yielded))
-            .ok_or_else(|| {
-                WithSpan(Spanned {
-                    span: actual.span,
-                    data: SyntaxError::UnexpectedToken {
-                        actual: actual.data.to_string(),
-                        // TODO: display all expected
-                        expected: want.to_string(),
-                    },
-                })
-            })
+        // An unexpected token is not consumed, so that error recovery can
+        // synchronize on it
+        match want.matching(&self.lexer.peek()?.data) {
+            Some(yielded) => Ok(self.lexer.next()?.map(|_| yielded)),
+            None => {
+                self.expect(&want);
+                Err(self.unexpected_token()?)
+            }
+        }
+    }
+
+    /// Remember that `want` was tried at the next token. If the token turns
+    /// out to be unexpected, all alternatives are listed in the error.
+    fn expect(&mut self, want: &impl ExpectedToken) {
+        let span = self.lexer.peek_span().ok();
+        if span != self.expected_span {
+            self.expected.clear();
+            self.expected_keywords.clear();
+            self.expected_span = span;
+        }
+
+        if let Some(keyword) = want.keyword() {
+            if !self.expected_keywords.contains(&keyword) {
+                self.expected_keywords.push(keyword);
+            }
+        }
+
+        let want = want.to_string();
+        if !self.expected.contains(&want) {
+            self.expected.push(want);
+        }
+    }
+
+    /// Create an error for the next token, which none of the alternatives
+    /// given to `expect` matched. An identifier that looks like one of the
+    /// expected keywords is probably a typo, e.g. `pubic`. So is a statement
+    /// that starts with an identifier that looks like a keyword, e.g.
+    /// `retrun 0;`.
+    fn unexpected_token(&mut self) -> SyntaxResult<'f, MaybeSpanned<'f, SyntaxError>> {
+        let actual = self.lexer.peek()?;
+        let (expected, keywords) = if Some(&actual.span) == self.expected_span.as_ref() {
+            (self.expected.clone(), self.expected_keywords.as_slice())
+        } else {
+            (Vec::new(), &[][..])
+        };
+        let suggestion = match &actual.data {
+            TokenKind::Identifier(name) => {
+                DidYouMean::closest(name, keywords.iter().map(|keyword| keyword.as_str()))
+            }
+            _ => DidYouMean::default(),
+        };
+        let suggestion = match (suggestion, &self.statement_start) {
+            (DidYouMean(None), Some(name)) => {
+                let keywords = Keyword::STATEMENT_START.iter().map(|keyword| keyword.as_str());
+                DidYouMean(
+                    closest_match(name, keywords)
+                        .map(|keyword| format!("'{}' instead of '{}'", keyword, name)),
+                )
+            }
+            (suggestion, _) => suggestion,
+        };
+
+        Ok(WithSpan(Spanned {
+            span: actual.span.clone(),
+            data: SyntaxError::UnexpectedToken {
+                actual: actual.data.to_string(),
+                expected: ExpectedTokens(expected),
+                suggestion,
+            },
+        }))
+    }
+
+    /// Check the next token without remembering `kind` as expected, e.g.
+    /// while skipping input during error recovery
+    fn next_is(&mut self, kind: &TokenKind) -> bool {
+        self.lexer.peek().ok().map_or(false, |token| &token.data == kind)
     }
 
     #[allow(clippy::needless_pass_by_value)]
@@ -259,10 +393,13 @@ where
 
         let got = self.lexer.peek()?;
 
-        Ok(want.matching(&got.data).filter(&pred).map(|yielded| {
-            let got = self.lexer.next().unwrap();
-            got.map(|_| yielded)
-        }))
+        match want.matching(&got.data).filter(&pred) {
+            Some(yielded) => Ok(Some(self.lexer.next()?.map(|_| yielded))),
+            None => {
+                self.expect(&want);
+                Ok(None)
+            }
+        }
     }
 
     #[allow(clippy::needless_pass_by_value)]
@@ -282,14 +419,116 @@ where
             return Ok(false);
         }
 
-        Ok(self.lexer.peek_nth(n).map(|got| want.matches(&got.data))?)
+        let matches = self.lexer.peek_nth(n).map(|got| want.matches(&got.data))?;
+        if !matches && n == 0 {
+            self.expect(&want);
+        }
+
+        Ok(matches)
     }
 
+    /// Parse the whole input and fail with the first syntax error.
     pub fn parse(&mut self) -> Result<ast::AST<'f>, MaybeSpanned<'f, SyntaxError>> {
+        let ast = self.parse_ast();
+        match self.errors.drain(..).next() {
+            Some(error) => Err(error),
+            None => Ok(ast),
+        }
+    }
+
+    /// Parse the whole input, recovering from syntax errors. Every error is
+    /// reported to `diagnostics` as soon as it is found. The returned AST is
+    /// partial if there were errors: malformed statements are replaced by
+    /// `Stmt::Error` nodes, and malformed class members and class declarations
+    /// are left out.
+    pub fn parse_with_diagnostics(&mut self, diagnostics: &'f Diagnostics) -> ast::AST<'f> {
+        self.diagnostics = Some(diagnostics);
+        self.parse_ast()
+    }
+
+    fn parse_ast(&mut self) -> ast::AST<'f> {
         if self.lexer.eof() {
-            Ok(ast::AST::Empty)
-        } else {
-            Ok(ast::AST::Program(self.parse_program()?))
+            return ast::AST::Empty;
+        }
+
+        match self.parse_program() {
+            Ok(program) => ast::AST::Program(program),
+            Err(error) => {
+                self.record(error);
+                ast::AST::Empty
+            }
+        }
+    }
+
+    /// Report a syntax error, or remember it if there are no diagnostics.
+    /// Every loop of the parser stops at the end of the file, so only the
+    /// first `UnexpectedEOF` is kept.
+    fn record(&mut self, error: MaybeSpanned<'f, SyntaxError>) {
+        let is_eof = |error: &MaybeSpanned<'f, SyntaxError>| match **error {
+            SyntaxError::UnexpectedEOF => true,
+            _ => false,
+        };
+
+        if !is_eof(&error) || !self.errors.iter().any(is_eof) {
+            if let Some(diagnostics) = self.diagnostics {
+                diagnostics.coded_error(&error);
+            }
+            self.errors.push(error);
+        }
+    }
+
+    /// Run `parse`. If it fails, the error is recorded and the parser is
+    /// synchronized with the input again. The span of the skipped input is
+    /// returned instead, so that the caller can insert an error node.
+    fn recovering<T, P>(&mut self, parse: P) -> Result<Spanned<'f, T>, Span<'f>>
+    where
+        P: FnOnce(&mut Self) -> ParserResult<'f, T>,
+    {
+        let start = self.lexer.peek_span().ok();
+        let error = match parse(self) {
+            Ok(node) => return Ok(node),
+            Err(error) => error,
+        };
+
+        self.record(error);
+        self.synchronize();
+
+        let end = self.lexer.prev_span();
+        Err(match (start, end) {
+            (Some(start), Some(end)) if end.end_position() >= start.start_position() => {
+                Span::new(start.start_position(), end.end_position())
+            }
+            // nothing was skipped, the parser is already in sync
+            (Some(start), _) => start,
+            (None, end) => end.expect("Bug! Recovery only happens after the first token"),
+        })
+    }
+
+    /// Panic-mode error recovery: skip tokens until the parser is likely in
+    /// sync with the input again. This is directly after a `;`, or after a
+    /// `{ ... }` that was opened while skipping, e.g. a method body. We also
+    /// stop before a `}` that closes an enclosing block or class, and before
+    /// the `public` keyword that starts the next class member.
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        while let Ok(token) = self.lexer.peek() {
+            match token.data {
+                TokenKind::Keyword(Keyword::Public) => return,
+                TokenKind::Operator(Operator::RightBrace) if depth == 0 => return,
+                _ => (),
+            }
+
+            match self.lexer.next().unwrap().data {
+                TokenKind::Operator(Operator::LeftBrace) => depth += 1,
+                TokenKind::Operator(Operator::RightBrace) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
+                }
+                TokenKind::Operator(Operator::Semicolon) if depth == 0 => return,
+                _ => (),
+            }
         }
     }
 
@@ -297,7 +536,20 @@ where
         spanned!(self, {
             let mut classes = Vec::new();
             while !self.lexer.eof() {
-                classes.push(self.parse_class_declaration()?);
+                match self.parse_class_declaration() {
+                    Ok(class) => classes.push(class),
+                    Err(error) => {
+                        // errors in class members are handled in
+                        // `parse_class_declaration`, so this is a malformed
+                        // class header. Skip the whole class.
+                        self.record(error);
+                        while !self.lexer.eof()
+                            && !self.next_is(&TokenKind::Keyword(Keyword::Class))
+                        {
+                            self.lexer.next()?;
+                        }
+                    }
+                }
             }
 
             Ok(ast::Program { classes })
@@ -315,7 +567,14 @@ where
                 .omnomnoptional(exactly(Operator::RightBrace))?
                 .is_none()
             {
-                members.push(self.parse_class_member()?);
+                if self.lexer.eof() {
+                    self.record(EOF.into());
+                    break;
+                }
+
+                if let Ok(member) = self.recovering(Self::parse_class_member) {
+                    members.push(member);
+                }
             }
 
             Ok(ast::ClassDeclaration { name, members })
@@ -413,14 +672,7 @@ where
         } else if let Some(sym) = self.omnomnoptional(Identifier)? {
             Ok(ast::BasicType::Custom(sym.data))
         } else {
-            let actual = self.lexer.next()?;
-            Err(WithSpan(Spanned {
-                span: actual.span,
-                data: SyntaxError::UnexpectedToken {
-                    actual: actual.data.to_string(),
-                    expected: "keyword `int`, `boolean`, `void` or an identifier".to_string(),
-                },
-            }))
+            Err(self.unexpected_token()?)
         }
     }
 
@@ -433,27 +685,63 @@ where
                 .omnomnoptional(exactly(Operator::RightBrace))?
                 .is_none()
             {
-                statements.push(self.parse_block_statement()?);
+                if self.lexer.eof() {
+                    self.record(EOF.into());
+                    break;
+                }
+
+                if self.next_is(&TokenKind::Keyword(Keyword::Public)) {
+                    // the `}` is missing and the next class member starts
+                    if let Err(error) = self.omnomnom(exactly(Operator::RightBrace)) {
+                        self.record(error);
+                    }
+                    break;
+                }
+
+                statements.push(self.parse_block_statement());
             }
 
             Ok(ast::Block { statements })
         })
     }
 
-    fn parse_statement(&mut self) -> ParserResult<'f, ast::Stmt<'f>> {
+    fn parse_statement(&mut self) -> Spanned<'f, ast::Stmt<'f>> {
         self.parse_statement_or_local_var(false)
     }
 
-    fn parse_block_statement(&mut self) -> ParserResult<'f, ast::Stmt<'f>> {
+    fn parse_block_statement(&mut self) -> Spanned<'f, ast::Stmt<'f>> {
         self.parse_statement_or_local_var(true)
     }
 
+    /// A malformed statement is replaced by a `Stmt::Error` node spanning
+    /// the skipped input, see `recovering`.
+    fn parse_statement_or_local_var(
+        &mut self,
+        allow_local_var_decl: bool,
+    ) -> Spanned<'f, ast::Stmt<'f>> {
+        let stmt = self
+            .recovering(|parser| parser.try_parse_statement_or_local_var(allow_local_var_decl))
+            .unwrap_or_else(|span| Spanned {
+                span,
+                data: ast::Stmt::Error,
+            });
+        // the statement is complete, errors after it are not caused by the
+        // identifier it started with
+        self.statement_start = None;
+        stmt
+    }
+
     // Using a bool-flag for *LocalVarDeclStatement* allows us to delay the
     // descision on weather the statement at point is a *LocalVarDeclStatement*
-    fn parse_statement_or_local_var(
+    fn try_parse_statement_or_local_var(
         &mut self,
         allow_local_var_decl: bool,
     ) -> ParserResult<'f, ast::Stmt<'f>> {
+        self.statement_start = match &self.lexer.peek()?.data {
+            TokenKind::Identifier(name) => Some(name.clone()),
+            _ => None,
+        };
+
         spanned!(self, {
             use self::ast::Stmt::*;
 
@@ -467,9 +755,9 @@ where
                 let cond = self.parse_expression()?;
                 self.omnomnom(exactly(Operator::RightParen))?;
 
-                let if_arm = self.parse_statement()?;
+                let if_arm = self.parse_statement();
                 let else_arm = if self.omnomnoptional(exactly(Keyword::Else))?.is_some() {
-                    Some(self.parse_statement()?)
+                    Some(self.parse_statement())
                 } else {
                     None
                 };
@@ -480,7 +768,7 @@ where
                 let cond = self.parse_expression()?;
                 self.omnomnom(exactly(Operator::RightParen))?;
 
-                let body = self.parse_statement()?;
+                let body = self.parse_statement();
 
                 Ok(While(cond, box body))
             } else if self.omnomnoptional(exactly(Keyword::Return))?.is_some() {
@@ -594,10 +882,17 @@ where
         }
 
         let mut expr = self.parse_postfix_expression()?;
+        // A parenthesized operand does not include the parentheses, but the
+        // unary expressions do. `sem::int_literals` relies on this to tell
+        // `-2147483648` from `-(2147483648)`.
+        let end = self
+            .lexer
+            .prev_span()
+            .expect("Bug! The operand consumed at least one token");
 
         for op in ops {
             expr = box Spanned {
-                span: Span::combine(&op.span, &expr.span),
+                span: Span::combine(&op.span, &end),
                 data: ast::Expr::Unary(op.data, expr),
             };
         }
@@ -705,13 +1000,7 @@ where
             } else if let Some(lit) = self.omnomnoptional(IntegerLiteral)? {
                 Ok(Int(lit.data))
             } else {
-                Err(WithSpan(Spanned {
-                    span: self.lexer.peek_span()?,
-                    data: SyntaxError::UnexpectedToken {
-                        actual: self.lexer.next()?.data.to_string(),
-                        expected: "primary expression".to_string(),
-                    },
-                }))
+                Err(self.unexpected_token()?)
             }
         })
         .map(Box::new)
@@ -934,9 +1223,105 @@ mod tests {
     fn else_with_empty_statement() {
         lex_input!(lx = r#"if(angry) {} else;"#);
         let mut p = Parser::new(lx);
-        p.parse_statement()
-            .map_err(|e| println!("{:?}", e))
-            .unwrap();
+        p.parse_statement();
+        assert!(p.errors.is_empty(), "{:?}", p.errors);
+    }
+
+    #[test]
+    fn recovers_from_syntax_errors() {
+        lex_input!(
+            lx = r#"
+            class Foo {
+                public int x
+                public void foo() {
+                    int a = 1 +;
+                    if (a) { a = 2 } else a = 3;
+                    return;
+                public boolean;
+                public void bar() {
+                    while (true) foo(;
+            }
+        "#
+        );
+        let mut p = Parser::new(lx);
+        let ast = p.parse_ast();
+
+        let errors: Vec<_> = p.errors.iter().map(|error| error.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "expected one of operator '(' or operator ';', found keyword 'public'",
+                "expected one of a unary operator, an identifier, operator '(', keyword 'new', \
+                 keyword 'null', keyword 'false', keyword 'true', keyword 'this' \
+                 or an integer literal, found operator ';'",
+                "expected one of operator '.', operator '[', a binary operator or operator ';', \
+                 found operator '}'",
+                "expected operator '}', found keyword 'public'",
+                "expected one of operator '[' or an identifier, found operator ';'",
+                "expected one of operator ')', a unary operator, an identifier, operator '(', \
+                 keyword 'new', keyword 'null', keyword 'false', keyword 'true', keyword 'this' \
+                 or an integer literal, found operator ';'",
+                "unexpected end of file",
+            ]
+        );
+
+        let class = match ast {
+            ast::AST::Program(program) => program.data.classes[0].data.clone(),
+            ast::AST::Empty => panic!("expected a partial AST"),
+        };
+        // the field `x` and the unnamed field are left out
+        assert_eq!(class.members.len(), 2);
+        let statements = match &class.members[0].data.kind {
+            ast::ClassMemberKind::Method(_, _, body) => &body.data.statements,
+            kind => panic!("expected method, got {:?}", kind),
+        };
+        assert_matches!(&statements[0].data, ast::Stmt::Error);
+        assert_matches!(&statements[1].data, ast::Stmt::If(..));
+        assert_matches!(&statements[2].data, ast::Stmt::Return(None));
+    }
+
+    #[test]
+    fn suggests_keywords_for_misspelled_identifiers() {
+        lex_input!(
+            lx = r#"
+            clas Foo {}
+            class Bar {
+                pubic int x;
+            }
+        "#
+        );
+        let mut p = Parser::new(lx);
+        p.parse_ast();
+
+        let errors: Vec<_> = p.errors.iter().map(|error| error.to_string()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "expected keyword 'class', found identifier 'clas'; did you mean 'class'?",
+                "expected one of operator '}' or keyword 'public', found identifier 'pubic'; \
+                 did you mean 'public'?",
+            ]
+        );
+    }
+
+    #[test]
+    fn expected_tokens_are_listed_with_or() {
+        let error = |expected: &[&str]| SyntaxError::UnexpectedToken {
+            actual: "identifier 'x'".to_string(),
+            expected: ExpectedTokens(expected.iter().map(|s| s.to_string()).collect()),
+            suggestion: DidYouMean(None),
+        };
+
+        assert_eq!(error(&[]).to_string(), "unexpected identifier 'x'");
+        assert_eq!(
+            error(&["operator ';'"]).to_string(),
+            "expected operator ';', found identifier 'x'"
+        );
+        assert_eq!(
+            error(&["operator ';'", "operator '='", "an identifier"]).to_string(),
+            "expected one of operator ';', operator '=' or an identifier, \
+             found identifier 'x'"
+        );
     }
 
     mod expr {
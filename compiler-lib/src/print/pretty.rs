@@ -193,6 +193,9 @@ fn do_prettyprint(n: &NodeKind<'_, '_>, printer: &mut IndentPrinter<'_>) {
                 Empty => {
                     printer.print_str(&";");
                 }
+                Error => {
+                    printer.print_str(&"/* syntax error */");
+                }
                 If(cond, stmt, opt_else) => {
                     printer.print_str(&"if (");
                     // no parenthesizes for expressions in if conditions
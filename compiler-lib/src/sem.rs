@@ -1,6 +0,0 @@
-use crate::{ast, context};
-use failure::Error;
-
-pub fn check<'a, 'f>(_ast: &'a ast::AST<'f>, _context: &context::Context<'_>) -> Result<(), Error> {
-    unimplemented!();
-}
@@ -0,0 +1,134 @@
+//! The prelude of built-in pseudo-classes that MiniJava programs can use
+//! without declaring them.
+//!
+//! MiniJava only borrows a tiny part of the Java standard library:
+//!
+//! * `String` is the element type of the main method's parameter. It is a type
+//!   without any members. User code must neither declare a class named `String`
+//!   nor instantiate it.
+//! * `System` is a global object with the fields `out` and `in`, which provide
+//!   `System.out.println(int)`, `System.out.write(int)`, `System.out.flush()`
+//!   and `System.in.read()`.
+//!
+//! The parser sees `System.out.println(x)` as an ordinary chain of `Var`,
+//! `FieldAccess` and `MethodInvocation` nodes. Name resolution binds `System`
+//! to `BuiltinClass::System` if it is not hidden by a local variable or
+//! field, and the type checker resolves the rest of the chain using the
+//! members defined here.
+use super::types::CheckedType;
+use std::fmt;
+
+pub const STRING: &str = "String";
+
+/// True if `name` refers to a built-in type that may be used in type
+/// annotations.
+pub fn is_builtin_type(name: &str) -> bool {
+    name == STRING
+}
+
+/// The built-in pseudo-classes whose instances are reachable from MiniJava
+/// code through the global `System` object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinClass {
+    System,
+    /// The type of `System.out`
+    PrintStream,
+    /// The type of `System.in`
+    InputStream,
+}
+
+/// A method of a `BuiltinClass`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinMethod {
+    /// `System.out.println(int)`: print a number followed by a newline
+    Println,
+    /// `System.out.write(int)`: write a single byte
+    Write,
+    /// `System.out.flush()`
+    Flush,
+    /// `System.in.read()`: read a single byte, `-1` on end of file
+    Read,
+}
+
+impl BuiltinClass {
+    /// The names of all globals of the prelude.
+    pub const GLOBALS: &'static [&'static str] = &["System"];
+
+    /// Look up a global name in the prelude.
+    pub fn global(name: &str) -> Option<BuiltinClass> {
+        match name {
+            "System" => Some(BuiltinClass::System),
+            _ => None,
+        }
+    }
+
+    pub fn field(self, name: &str) -> Option<BuiltinClass> {
+        match (self, name) {
+            (BuiltinClass::System, "out") => Some(BuiltinClass::PrintStream),
+            (BuiltinClass::System, "in") => Some(BuiltinClass::InputStream),
+            _ => None,
+        }
+    }
+
+    pub fn field_names(self) -> &'static [&'static str] {
+        match self {
+            BuiltinClass::System => &["out", "in"],
+            BuiltinClass::PrintStream | BuiltinClass::InputStream => &[],
+        }
+    }
+
+    pub fn method(self, name: &str) -> Option<BuiltinMethod> {
+        match (self, name) {
+            (BuiltinClass::PrintStream, "println") => Some(BuiltinMethod::Println),
+            (BuiltinClass::PrintStream, "write") => Some(BuiltinMethod::Write),
+            (BuiltinClass::PrintStream, "flush") => Some(BuiltinMethod::Flush),
+            (BuiltinClass::InputStream, "read") => Some(BuiltinMethod::Read),
+            _ => None,
+        }
+    }
+
+    pub fn method_names(self) -> &'static [&'static str] {
+        match self {
+            BuiltinClass::System => &[],
+            BuiltinClass::PrintStream => &["println", "write", "flush"],
+            BuiltinClass::InputStream => &["read"],
+        }
+    }
+}
+
+impl fmt::Display for BuiltinClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuiltinClass::System => write!(f, "System"),
+            BuiltinClass::PrintStream => write!(f, "PrintStream"),
+            BuiltinClass::InputStream => write!(f, "InputStream"),
+        }
+    }
+}
+
+impl BuiltinMethod {
+    pub fn name(self) -> &'static str {
+        match self {
+            BuiltinMethod::Println => "println",
+            BuiltinMethod::Write => "write",
+            BuiltinMethod::Flush => "flush",
+            BuiltinMethod::Read => "read",
+        }
+    }
+
+    pub fn param_types(self) -> Vec<CheckedType> {
+        match self {
+            BuiltinMethod::Println | BuiltinMethod::Write => vec![CheckedType::Int],
+            BuiltinMethod::Flush | BuiltinMethod::Read => vec![],
+        }
+    }
+
+    pub fn return_type(self) -> CheckedType {
+        match self {
+            BuiltinMethod::Println | BuiltinMethod::Write | BuiltinMethod::Flush => {
+                CheckedType::Void
+            }
+            BuiltinMethod::Read => CheckedType::Int,
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! The class table is the global symbol table of a MiniJava program. It maps
+//! class names to their declarations and, per class, field and method names
+//! to the members declaring them.
+//!
+//! Fields and methods live in separate namespaces, i.e. a class may declare a
+//! field and a method of the same name.
+use super::{builtins, SemanticError};
+use crate::{
+    asciifile::Spanned,
+    ast,
+    context::Context,
+    diagnostics::{Message, MessageLevel},
+    error_codes::Coded,
+    strtab::Symbol,
+};
+use std::collections::HashMap;
+
+pub type MemberRef<'a, 'f> = &'a Spanned<'f, ast::ClassMember<'f>>;
+
+#[derive(Debug)]
+pub struct ClassDef<'a, 'f> {
+    pub decl: &'a Spanned<'f, ast::ClassDeclaration<'f>>,
+    pub fields: HashMap<Symbol, MemberRef<'a, 'f>>,
+    pub methods: HashMap<Symbol, MemberRef<'a, 'f>>,
+}
+
+impl<'a, 'f> ClassDef<'a, 'f> {
+    fn collect_members(
+        decl: &'a Spanned<'f, ast::ClassDeclaration<'f>>,
+        context: &Context<'_>,
+    ) -> Self {
+        let mut class = ClassDef {
+            decl,
+            fields: HashMap::new(),
+            methods: HashMap::new(),
+        };
+
+        for member in &decl.members {
+            use crate::ast::ClassMemberKind::*;
+            let namespace = match member.kind {
+                Field(_) => &mut class.fields,
+                Method(..) | MainMethod(..) => &mut class.methods,
+            };
+
+            let first = match namespace.get(&member.name) {
+                Some(first) => first,
+                None => {
+                    namespace.insert(member.name.clone(), member);
+                    continue;
+                }
+            };
+
+            let name = member.name.to_string();
+            let class_name = decl.name.to_string();
+            let error = Spanned {
+                span: member.span.clone(),
+                data: match member.kind {
+                    Field(_) => SemanticError::RedefinitionOfField { name, class_name },
+                    Method(..) | MainMethod(..) => {
+                        SemanticError::RedefinitionOfMethod { name, class_name }
+                    }
+                },
+            };
+            context.diagnostics.emit_message(
+                &Message::new(MessageLevel::Error, &error)
+                    .with_code(error.code())
+                    .with_label(first.span.first_line(), "first declared here"),
+            );
+        }
+
+        class
+    }
+
+    pub fn name(&self) -> &Symbol {
+        &self.decl.name
+    }
+
+    pub fn field(&self, name: &str) -> Option<MemberRef<'a, 'f>> {
+        self.fields.get(name).cloned()
+    }
+
+    pub fn method(&self, name: &str) -> Option<MemberRef<'a, 'f>> {
+        self.methods.get(name).cloned()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ClassTable<'a, 'f> {
+    classes: HashMap<Symbol, ClassDef<'a, 'f>>,
+}
+
+impl<'a, 'f> ClassTable<'a, 'f> {
+    /// Collect all classes and their members. Redefinitions are reported to
+    /// `context.diagnostics`, the first definition wins. Classes that shadow
+    /// a built-in class are reported and left out of the table.
+    pub fn build(program: &'a Spanned<'f, ast::Program<'f>>, context: &Context<'_>) -> Self {
+        let mut table = ClassTable::default();
+
+        for decl in &program.classes {
+            if builtins::is_builtin_type(&decl.name) {
+                context.diagnostics.coded_error(&Spanned {
+                    span: decl.span.clone(),
+                    data: SemanticError::ShadowsBuiltinClass {
+                        name: decl.name.to_string(),
+                    },
+                });
+                continue;
+            }
+
+            if let Some(first) = table.classes.get(&decl.name) {
+                let error = Spanned {
+                    span: decl.span.clone(),
+                    data: SemanticError::RedefinitionOfClass {
+                        name: decl.name.to_string(),
+                    },
+                };
+                context.diagnostics.emit_message(
+                    &Message::new(MessageLevel::Error, &error)
+                        .with_code(error.code())
+                        .with_label(first.decl.span.first_line(), "first declared here"),
+                );
+                continue;
+            }
+
+            let class = ClassDef::collect_members(decl, context);
+            table.classes.insert(decl.name.clone(), class);
+        }
+
+        table
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ClassDef<'a, 'f>> {
+        self.classes.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.classes.contains_key(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ClassDef<'a, 'f>> {
+        self.classes.values()
+    }
+}
@@ -0,0 +1,91 @@
+//! Definite-return and reachability analysis of method bodies.
+//!
+//! A statement *can complete normally* if execution may continue with the
+//! statement following it. Since MiniJava has neither `break` nor
+//! exceptions, this analysis is a simplified version of the one in chapter
+//! 14.21 of the Java Language Specification:
+//!
+//! * `return` never completes normally.
+//! * `while (true)` never completes normally, every other loop may.
+//! * `if` without `else` may always complete normally, `if` with `else` if any
+//!   of its arms may.
+//! * A block completes normally if all of its statements do.
+//!
+//! A non-void method whose body can complete normally is missing a return
+//! statement. Statements that follow a statement that cannot complete
+//! normally are unreachable, which is reported as a warning.
+use super::{SemanticError, SemanticWarning};
+use crate::{
+    asciifile::{Span, Spanned},
+    ast,
+    context::Context,
+};
+
+pub fn check<'f>(program: &Spanned<'f, ast::Program<'f>>, context: &Context<'_>) {
+    for decl in &program.classes {
+        for member in &decl.members {
+            match &member.kind {
+                ast::ClassMemberKind::Method(ty, _, body) => {
+                    let completes_normally = check_block(body, context);
+                    let is_void = ty.basic == ast::BasicType::Void && ty.array_depth == 0;
+                    if completes_normally && !is_void {
+                        // point at the closing brace of the method body
+                        context.diagnostics.coded_error(&Spanned {
+                            span: Span::from_single_position(body.span.end_position()),
+                            data: SemanticError::MissingReturn {
+                                name: member.name.to_string(),
+                            },
+                        });
+                    }
+                }
+                ast::ClassMemberKind::MainMethod(_, body) => {
+                    check_block(body, context);
+                }
+                ast::ClassMemberKind::Field(_) => (),
+            }
+        }
+    }
+}
+
+/// Report unreachable statements in `block` and return whether it can
+/// complete normally.
+fn check_block(block: &Spanned<'_, ast::Block<'_>>, context: &Context<'_>) -> bool {
+    let mut completes_normally = true;
+    for stmt in &block.statements {
+        if !completes_normally {
+            // only warn about the first unreachable statement of a block,
+            // the others are unreachable for the same reason
+            context.diagnostics.lint(&Spanned {
+                span: stmt.span.clone(),
+                data: SemanticWarning::UnreachableStatement,
+            });
+            break;
+        }
+        completes_normally = check_stmt(stmt, context);
+    }
+    completes_normally
+}
+
+/// Report unreachable statements nested in `stmt` and return whether it can
+/// complete normally.
+fn check_stmt(stmt: &Spanned<'_, ast::Stmt<'_>>, context: &Context<'_>) -> bool {
+    use crate::ast::Stmt::*;
+    match &stmt.data {
+        Block(block) => check_block(block, context),
+        Return(_) => false,
+        If(_, then_arm, None) => {
+            check_stmt(then_arm, context);
+            true
+        }
+        If(_, then_arm, Some(else_arm)) => {
+            let then_completes = check_stmt(then_arm, context);
+            let else_completes = check_stmt(else_arm, context);
+            then_completes || else_completes
+        }
+        While(cond, body) => {
+            check_stmt(body, context);
+            cond.data != ast::Expr::Boolean(true)
+        }
+        Empty | Expression(_) | LocalVariableDeclaration(..) | Error => true,
+    }
+}
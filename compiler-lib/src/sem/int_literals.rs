@@ -0,0 +1,109 @@
+//! Range checking and parsing of integer literals.
+//!
+//! The lexer accepts arbitrarily long sequences of digits, so the range of
+//! MiniJava's 32 bit integers is checked here. As in Java, `2147483648` is
+//! only valid as the operand of a unary minus, because the parser has no
+//! negative literals and `-2147483648` is the minimum value of `int`. The
+//! operand must not be parenthesized, `-(2147483648)` is out of range.
+//!
+//! The parsed values are stored in `IntLiterals`, a side table from every
+//! `Expr::Int` node to its value. The literal in `-2147483648` is stored as
+//! `i32::MIN`, so later phases get the correct result by negating it with
+//! wrapping arithmetic, which is what MiniJava's `int` uses anyway.
+use super::SemanticError;
+use crate::{asciifile::Spanned, ast, context::Context, utils::RefEquality, visitor::NodeKind};
+use std::collections::HashMap;
+
+#[derive(Debug, Default)]
+pub struct IntLiterals<'a, 'f> {
+    values: HashMap<RefEquality<'a, Spanned<'f, ast::Expr<'f>>>, i32>,
+}
+
+impl<'a, 'f> IntLiterals<'a, 'f> {
+    /// Get the value of an `Expr::Int` node. Returns `None` for other
+    /// expressions and for literals that are out of range.
+    pub fn value(&self, expr: &'a Spanned<'f, ast::Expr<'f>>) -> Option<i32> {
+        self.values.get(&RefEquality(expr)).cloned()
+    }
+}
+
+pub fn check<'a, 'f>(
+    program: &'a Spanned<'f, ast::Program<'f>>,
+    context: &Context<'_>,
+) -> IntLiterals<'a, 'f> {
+    let mut literals = IntLiterals::default();
+    visit(&NodeKind::from(program), &mut literals, context);
+    literals
+}
+
+fn visit<'a, 'f>(
+    node: &NodeKind<'a, 'f>,
+    literals: &mut IntLiterals<'a, 'f>,
+    context: &Context<'_>,
+) {
+    if let NodeKind::Expr(expr) = node {
+        match &expr.data {
+            ast::Expr::Unary(ast::UnaryOp::Neg, operand) => {
+                if let ast::Expr::Int(digits) = &operand.data {
+                    // the AST has no parentheses, but the span of the negation
+                    // includes them, see `Parser::parse_unary_expression`
+                    let parenthesized = expr.span.end_position() != operand.span.end_position();
+                    check_literal(operand, digits, !parenthesized, literals, context);
+                    return;
+                }
+            }
+            ast::Expr::Int(digits) => {
+                check_literal(expr, digits, false, literals, context);
+                return;
+            }
+            _ => (),
+        }
+    }
+
+    node.for_each_child(&mut |child| visit(&child, literals, context));
+}
+
+fn check_literal<'a, 'f>(
+    literal: &'a Spanned<'f, ast::Expr<'f>>,
+    digits: &str,
+    negated: bool,
+    literals: &mut IntLiterals<'a, 'f>,
+    context: &Context<'_>,
+) {
+    match parse(digits, negated) {
+        Some(value) => {
+            literals.values.insert(RefEquality(literal), value);
+        }
+        None => context.diagnostics.coded_error(&Spanned {
+            span: literal.span.clone(),
+            data: SemanticError::IntegerLiteralOutOfRange {
+                literal: digits.to_string(),
+            },
+        }),
+    }
+}
+
+/// Parse the digits of an integer literal. If the literal is the operand of
+/// a unary minus, `2147483648` is accepted and parsed as `i32::MIN`.
+fn parse(digits: &str, negated: bool) -> Option<i32> {
+    match digits.parse::<i32>() {
+        Ok(value) => Some(value),
+        Err(_) if negated && digits == "2147483648" => Some(i32::min_value()),
+        Err(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_checks_the_range_of_int() {
+        assert_eq!(parse("0", false), Some(0));
+        assert_eq!(parse("2147483647", false), Some(i32::max_value()));
+        assert_eq!(parse("2147483648", false), None);
+        assert_eq!(parse("2147483648", true), Some(i32::min_value()));
+        assert_eq!(parse("2147483649", true), None);
+        assert_eq!(parse("99999999999999999999999", true), None);
+    }
+}
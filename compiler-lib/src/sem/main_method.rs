@@ -0,0 +1,145 @@
+//! Checks for the rules MiniJava imposes on the main method.
+//!
+//! The grammar accepts any number of `public static void NAME(String[] x)`
+//! members, but a valid program contains exactly one of them and it must be
+//! named `main`. The main method is static, so it must not refer to `this`,
+//! neither explicitly nor implicitly by calling a method or using a field
+//! without a qualifying object.
+//!
+//! All violations are reported at the span of the offending main method.
+use super::{
+    class_table::{ClassDef, ClassTable},
+    name_resolution::{NameResolution, VarDef},
+    SemanticError,
+};
+use crate::{
+    asciifile::{MaybeSpanned, Spanned},
+    ast,
+    context::Context,
+    diagnostics::{Message, MessageLevel},
+    error_codes::Coded,
+    visitor::NodeKind,
+};
+
+pub const MAIN_METHOD_NAME: &str = "main";
+
+pub fn check<'a, 'f>(
+    program: &'a Spanned<'f, ast::Program<'f>>,
+    classes: &ClassTable<'a, 'f>,
+    names: &NameResolution<'a, 'f>,
+    context: &Context<'_>,
+) {
+    let mut main_methods = program
+        .classes
+        .iter()
+        .flat_map(|decl| decl.members.iter().map(move |member| (decl, member)))
+        .filter(|(_, member)| is_main_method(member))
+        .peekable();
+
+    if main_methods.peek().is_none() {
+        report_missing_main_method(context);
+        return;
+    }
+
+    let mut first_main_method = None;
+    for (decl, member) in main_methods {
+        match first_main_method {
+            None => first_main_method = Some(member),
+            Some(first) => {
+                let error = Spanned {
+                    span: member.span.clone(),
+                    data: SemanticError::MultipleMainMethods,
+                };
+                context.diagnostics.emit_message(
+                    &Message::new(MessageLevel::Error, &error)
+                        .with_code(error.code())
+                        .with_label(first.span.first_line(), "first main method declared here"),
+                );
+            }
+        }
+        if &*member.name != MAIN_METHOD_NAME {
+            error(
+                context,
+                member,
+                SemanticError::MainMethodNotNamedMain {
+                    name: member.name.to_string(),
+                },
+            );
+        }
+
+        let mut checker = StaticContextChecker {
+            context,
+            names,
+            class: classes.get(&decl.name),
+            member,
+        };
+        checker.visit(&NodeKind::from(member));
+    }
+}
+
+/// A program without classes is syntactically valid, but it has no main
+/// method either.
+pub fn report_missing_main_method(context: &Context<'_>) {
+    let error = MaybeSpanned::WithoutSpan(SemanticError::MissingMainMethod);
+    context.diagnostics.emit_message(
+        &Message::new(MessageLevel::Error, &error)
+            .with_code(error.code())
+            .with_help("add `public static void main(String[] args) { }` to a class"),
+    );
+}
+
+/// Reports every explicit or implicit use of `this` in the body of a main
+/// method.
+struct StaticContextChecker<'a, 'f, 'cx> {
+    context: &'cx Context<'cx>,
+    names: &'cx NameResolution<'a, 'f>,
+    class: Option<&'cx ClassDef<'a, 'f>>,
+    member: &'a Spanned<'f, ast::ClassMember<'f>>,
+}
+
+impl<'a, 'f, 'cx> StaticContextChecker<'a, 'f, 'cx> {
+    fn visit(&mut self, node: &NodeKind<'a, 'f>) {
+        if let NodeKind::Expr(expr) = node {
+            self.check_expr(expr);
+        }
+        node.for_each_child(&mut |child| self.visit(&child));
+    }
+
+    fn check_expr(&self, expr: &'a Spanned<'f, ast::Expr<'f>>) {
+        use crate::ast::Expr::*;
+        let kind = match &expr.data {
+            This => SemanticError::ThisInMainMethod,
+            ThisMethodInvocation(name, _) => match self.class.and_then(|c| c.method(name)) {
+                // calling another static method is fine
+                Some(method) if is_main_method(method) => return,
+                // undeclared methods are reported by name resolution
+                None => return,
+                Some(_) => SemanticError::NonStaticMethodCallInMainMethod {
+                    name: name.to_string(),
+                },
+            },
+            Var(name) => match self.names.var_def(expr) {
+                Some(VarDef::Field(_)) => SemanticError::NonStaticFieldInMainMethod {
+                    name: name.to_string(),
+                },
+                _ => return,
+            },
+            _ => return,
+        };
+        error(self.context, self.member, kind);
+    }
+}
+
+fn is_main_method(member: &ast::ClassMember<'_>) -> bool {
+    match member.kind {
+        ast::ClassMemberKind::MainMethod(..) => true,
+        _ => false,
+    }
+}
+
+fn error(context: &Context<'_>, member: &Spanned<'_, ast::ClassMember<'_>>, kind: SemanticError) {
+    context.diagnostics.coded_error(&Spanned {
+        span: member.span.clone(),
+        data: kind,
+    });
+}
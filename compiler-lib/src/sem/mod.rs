@@ -0,0 +1,231 @@
+//! Semantic analysis of MiniJava programs.
+//!
+//! The analysis runs in multiple passes over the AST:
+//!
+//! 1. The `ClassTable` collects all classes and their members.
+//! 2. Name resolution binds every variable use to its definition, which may
+//!    also be one of the `builtins`, e.g. `System`.
+//! 3. The main method rules are checked: a program has exactly one main method,
+//!    it is named `main` and it does not use `this`.
+//! 4. The type checker computes the type of every expression and checks that it
+//!    is used correctly.
+//! 5. The control flow analysis checks that non-void methods always return a
+//!    value and warns about unreachable statements.
+//! 6. Integer literals are range checked and parsed.
+//!
+//! All errors and warnings are reported to `context.diagnostics`. The passes
+//! try to continue after an error to report as many errors as possible in one
+//! run.
+mod builtins;
+mod class_table;
+mod control_flow;
+mod int_literals;
+mod main_method;
+mod name_resolution;
+mod symtab;
+mod type_checking;
+mod types;
+
+pub use self::{
+    builtins::{BuiltinClass, BuiltinMethod},
+    class_table::{ClassDef, ClassTable},
+    int_literals::IntLiterals,
+    name_resolution::{NameResolution, VarDef},
+    symtab::SymbolTable,
+    type_checking::TypeAnalysis,
+    types::CheckedType,
+};
+
+use crate::{
+    asciifile::Spanned,
+    ast, context,
+    error_codes::{Coded, ErrorCode},
+    lints::{self, Lint, Linted},
+    spelling::DidYouMean,
+};
+use failure::{Error, Fail};
+
+#[rustfmt::skip]
+#[derive(Debug, Fail)]
+pub enum SemanticError {
+    #[fail(display = "redefinition of class '{}'", name)]
+    RedefinitionOfClass { name: String },
+    #[fail(display = "redefinition of field '{}' in class '{}'", name, class_name)]
+    RedefinitionOfField { name: String, class_name: String },
+    #[fail(display = "redefinition of method '{}' in class '{}'", name, class_name)]
+    RedefinitionOfMethod { name: String, class_name: String },
+    #[fail(display = "redefinition of parameter '{}'", name)]
+    RedefinitionOfParameter { name: String },
+    #[fail(display = "redefinition of local variable '{}'", name)]
+    RedefinitionOfLocal { name: String },
+    #[fail(display = "local variable '{}' shadows a parameter", name)]
+    LocalShadowsParameter { name: String },
+    #[fail(display = "cannot find variable '{}' in this scope{}", name, suggestion)]
+    UndeclaredVariable { name: String, suggestion: DidYouMean },
+    #[fail(display = "class '{}' has no method '{}'{}", class_name, name, suggestion)]
+    UndeclaredMethod { name: String, class_name: String, suggestion: DidYouMean },
+    #[fail(display = "cannot find type '{}'{}", name, suggestion)]
+    UndeclaredType { name: String, suggestion: DidYouMean },
+    #[fail(display = "class '{}' has no field '{}'{}", class_name, name, suggestion)]
+    UndeclaredField { name: String, class_name: String, suggestion: DidYouMean },
+    #[fail(display = "mismatched types: expected '{}', found '{}'", expected, actual)]
+    MismatchedTypes { expected: String, actual: String },
+    #[fail(display = "cannot compare '{}' with '{}'", lhs, rhs)]
+    InvalidComparison { lhs: String, rhs: String },
+    #[fail(display = "cannot call a method on a value of type '{}'", ty)]
+    MethodCallOnNonObject { ty: String },
+    #[fail(display = "cannot access a field of a value of type '{}'", ty)]
+    FieldAccessOnNonObject { ty: String },
+    #[fail(display = "cannot index into a value of type '{}'", ty)]
+    IndexOnNonArray { ty: String },
+    #[fail(display = "method '{}' takes {} argument(s) but {} were supplied", name, expected, actual)]
+    WrongNumberOfArguments { name: String, expected: usize, actual: usize },
+    #[fail(display = "invalid left-hand side of assignment")]
+    NotAssignable,
+    #[fail(display = "'void' is only allowed as the return type of a method")]
+    VoidNotAllowed,
+    #[fail(display = "missing return value of type '{}'", ty)]
+    MissingReturnValue { ty: String },
+    #[fail(display = "cannot return a value from a method returning 'void'")]
+    UnexpectedReturnValue,
+    #[fail(display = "class '{}' shadows a built-in class", name)]
+    ShadowsBuiltinClass { name: String },
+    #[fail(display = "cannot instantiate built-in class '{}'", name)]
+    InstantiationOfBuiltinClass { name: String },
+    #[fail(display = "the parameter '{}' of the main method cannot be used", name)]
+    UseOfMainParameter { name: String },
+    #[fail(display = "no main method found")]
+    MissingMainMethod,
+    #[fail(display = "a program must contain exactly one main method")]
+    MultipleMainMethods,
+    #[fail(display = "the main method must be named 'main', not '{}'", name)]
+    MainMethodNotNamedMain { name: String },
+    #[fail(display = "cannot use 'this' in the static main method")]
+    ThisInMainMethod,
+    #[fail(display = "cannot call non-static method '{}' from the static main method", name)]
+    NonStaticMethodCallInMainMethod { name: String },
+    #[fail(display = "cannot use non-static field '{}' in the static main method", name)]
+    NonStaticFieldInMainMethod { name: String },
+    #[fail(display = "missing return statement in method '{}'", name)]
+    MissingReturn { name: String },
+    #[fail(display = "integer literal '{}' is out of range for type 'int'", literal)]
+    IntegerLiteralOutOfRange { literal: String },
+}
+
+impl Coded for SemanticError {
+    fn code(&self) -> ErrorCode {
+        use self::SemanticError::*;
+        match self {
+            RedefinitionOfClass { .. } => ErrorCode(301),
+            RedefinitionOfField { .. } => ErrorCode(302),
+            RedefinitionOfMethod { .. } => ErrorCode(303),
+            RedefinitionOfParameter { .. } => ErrorCode(304),
+            RedefinitionOfLocal { .. } => ErrorCode(305),
+            LocalShadowsParameter { .. } => ErrorCode(306),
+            UndeclaredVariable { .. } => ErrorCode(307),
+            UndeclaredMethod { .. } => ErrorCode(308),
+            UndeclaredType { .. } => ErrorCode(309),
+            UndeclaredField { .. } => ErrorCode(310),
+            MismatchedTypes { .. } => ErrorCode(311),
+            InvalidComparison { .. } => ErrorCode(312),
+            MethodCallOnNonObject { .. } => ErrorCode(313),
+            FieldAccessOnNonObject { .. } => ErrorCode(314),
+            IndexOnNonArray { .. } => ErrorCode(315),
+            WrongNumberOfArguments { .. } => ErrorCode(316),
+            NotAssignable => ErrorCode(317),
+            VoidNotAllowed => ErrorCode(318),
+            MissingReturnValue { .. } => ErrorCode(319),
+            UnexpectedReturnValue => ErrorCode(320),
+            ShadowsBuiltinClass { .. } => ErrorCode(321),
+            InstantiationOfBuiltinClass { .. } => ErrorCode(322),
+            UseOfMainParameter { .. } => ErrorCode(323),
+            MissingMainMethod => ErrorCode(324),
+            MultipleMainMethods => ErrorCode(325),
+            MainMethodNotNamedMain { .. } => ErrorCode(326),
+            ThisInMainMethod => ErrorCode(327),
+            NonStaticMethodCallInMainMethod { .. } => ErrorCode(328),
+            NonStaticFieldInMainMethod { .. } => ErrorCode(329),
+            MissingReturn { .. } => ErrorCode(330),
+            IntegerLiteralOutOfRange { .. } => ErrorCode(331),
+        }
+    }
+}
+
+#[rustfmt::skip]
+#[derive(Debug, Fail)]
+pub enum SemanticWarning {
+    #[fail(display = "unreachable statement")]
+    UnreachableStatement,
+}
+
+impl Linted for SemanticWarning {
+    fn lint(&self) -> &'static Lint {
+        match self {
+            SemanticWarning::UnreachableStatement => &lints::UNREACHABLE_STATEMENT,
+        }
+    }
+}
+
+/// The result of the semantic analysis of a program. It provides the
+/// information computed by the individual passes to later compiler phases.
+#[derive(Debug)]
+pub struct TypedProgram<'a, 'f> {
+    pub program: &'a Spanned<'f, ast::Program<'f>>,
+    pub classes: ClassTable<'a, 'f>,
+    pub names: NameResolution<'a, 'f>,
+    pub types: TypeAnalysis<'a, 'f>,
+    pub literals: IntLiterals<'a, 'f>,
+}
+
+impl<'a, 'f> TypedProgram<'a, 'f> {
+    /// Get the type of an expression of `self.program`.
+    pub fn expr_type(&self, expr: &'a Spanned<'f, ast::Expr<'f>>) -> &CheckedType {
+        self.types
+            .expr_type(expr)
+            .expect("every expression of a program has a type")
+    }
+
+    /// Get the value of an `Expr::Int` node of `self.program`.
+    pub fn int_value(&self, expr: &'a Spanned<'f, ast::Expr<'f>>) -> i32 {
+        self.literals
+            .value(expr)
+            .expect("every integer literal of a valid program has a value")
+    }
+}
+
+/// Run all passes of the semantic analysis on `program`. Errors are reported
+/// to `context.diagnostics`, the returned `TypedProgram` is only meaningful
+/// if no errors were reported.
+pub fn analyze<'a, 'f>(
+    program: &'a Spanned<'f, ast::Program<'f>>,
+    context: &context::Context<'_>,
+) -> TypedProgram<'a, 'f> {
+    let classes = ClassTable::build(program, context);
+    let names = name_resolution::resolve(program, &classes, context);
+    main_method::check(program, &classes, &names, context);
+    let types = type_checking::check(program, &classes, &names, context);
+    control_flow::check(program, context);
+    let literals = int_literals::check(program, context);
+
+    TypedProgram {
+        program,
+        classes,
+        names,
+        types,
+        literals,
+    }
+}
+
+pub fn check<'a, 'f>(ast: &'a ast::AST<'f>, context: &context::Context<'_>) -> Result<(), Error> {
+    let program = match ast {
+        ast::AST::Empty => {
+            main_method::report_missing_main_method(context);
+            return Ok(());
+        }
+        ast::AST::Program(program) => program,
+    };
+
+    analyze(program, context);
+
+    Ok(())
+}
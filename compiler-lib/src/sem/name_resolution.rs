@@ -0,0 +1,307 @@
+//! Name resolution binds every use of a name in a method body to its
+//! definition.
+//!
+//! Local variables and parameters are tracked in a scoped `SymbolTable`:
+//! every method opens a scope for its parameters and every `Block` opens a
+//! nested scope for the local variables declared in it. A name that cannot
+//! be found in any of these scopes is looked up in the fields of the
+//! enclosing class.
+//!
+//! If the name is not a field either, it may refer to one of the `builtins`,
+//! i.e. `System`. The parameter of the main method is in scope, but using it
+//! is an error.
+//!
+//! Like in Java, a local variable must not have the same name as another
+//! local variable or parameter that is still in scope. It may, however, hide
+//! a field.
+use super::{
+    builtins::{self, BuiltinClass},
+    class_table::{ClassDef, ClassTable},
+    symtab::SymbolTable,
+    SemanticError,
+};
+use crate::{
+    asciifile::{Span, Spanned},
+    ast,
+    context::Context,
+    diagnostics::{Message, MessageLevel},
+    error_codes::Coded,
+    lexer::Keyword,
+    spelling::DidYouMean,
+    strtab::Symbol,
+    utils::RefEquality,
+    visitor::NodeKind,
+};
+use std::collections::HashMap;
+
+/// The definition a variable name refers to.
+#[derive(Debug, Clone, Copy)]
+pub enum VarDef<'a, 'f> {
+    Field(&'a Spanned<'f, ast::ClassMember<'f>>),
+    Param(&'a Spanned<'f, ast::Parameter<'f>>),
+    /// The `String[]` parameter of the main method. It is the only
+    /// parameter that has no `ast::Parameter` node.
+    MainParam(&'a Spanned<'f, ast::ClassMember<'f>>),
+    /// A `Stmt::LocalVariableDeclaration`
+    Local(&'a Spanned<'f, ast::Stmt<'f>>),
+    /// A global object of the prelude
+    Builtin(BuiltinClass),
+}
+
+/// The result of name resolution: a side table from each `Expr::Var` to the
+/// definition it refers to.
+#[derive(Debug, Default)]
+pub struct NameResolution<'a, 'f> {
+    vars: HashMap<RefEquality<'a, Spanned<'f, ast::Expr<'f>>>, VarDef<'a, 'f>>,
+}
+
+impl<'a, 'f> NameResolution<'a, 'f> {
+    /// Get the definition of a `Expr::Var` node. Returns `None` if `expr` is
+    /// not a variable or refers to an undeclared name.
+    pub fn var_def(&self, expr: &'a Spanned<'f, ast::Expr<'f>>) -> Option<VarDef<'a, 'f>> {
+        self.vars.get(&RefEquality(expr)).cloned()
+    }
+}
+
+pub fn resolve<'a, 'f>(
+    program: &'a Spanned<'f, ast::Program<'f>>,
+    classes: &ClassTable<'a, 'f>,
+    context: &Context<'_>,
+) -> NameResolution<'a, 'f> {
+    let mut resolver = NameResolver {
+        context,
+        classes,
+        current_class: None,
+        locals: SymbolTable::new(),
+        statement_expr: None,
+        resolution: NameResolution::default(),
+    };
+    resolver.visit(&NodeKind::from(program));
+    resolver.resolution
+}
+
+struct NameResolver<'a, 'f, 'cx> {
+    context: &'cx Context<'cx>,
+    classes: &'cx ClassTable<'a, 'f>,
+    current_class: Option<&'cx ClassDef<'a, 'f>>,
+    locals: SymbolTable<VarDef<'a, 'f>>,
+    /// The expression of the innermost expression statement. An undeclared
+    /// variable that makes up a whole statement may be a misspelled keyword.
+    statement_expr: Option<RefEquality<'a, Spanned<'f, ast::Expr<'f>>>>,
+    resolution: NameResolution<'a, 'f>,
+}
+
+impl<'a, 'f, 'cx> NameResolver<'a, 'f, 'cx> {
+    fn visit(&mut self, node: &NodeKind<'a, 'f>) {
+        use crate::visitor::NodeKind::*;
+        match node {
+            ClassDeclaration(decl) => {
+                // redefinitions of a class are not part of the class table, but
+                // their bodies are still checked against the first definition
+                self.current_class = self.classes.get(&decl.name);
+                self.visit_children(node);
+                self.current_class = None;
+            }
+
+            ClassMember(member) => {
+                self.locals.enter_scope();
+                if let ast::ClassMemberKind::MainMethod(param, _) = &member.kind {
+                    self.declare(param, VarDef::MainParam(member), &member.span);
+                }
+                self.visit_children(node);
+                self.locals.leave_scope();
+            }
+
+            Parameter(param) => {
+                self.visit_children(node);
+                self.declare(&param.name, VarDef::Param(param), &param.span);
+            }
+
+            Block(_) => {
+                self.locals.enter_scope();
+                self.visit_children(node);
+                self.locals.leave_scope();
+            }
+
+            Stmt(stmt) => match &stmt.data {
+                ast::Stmt::LocalVariableDeclaration(ty, name, init) => {
+                    // a type in statement position, e.g. `retrun x;`
+                    self.check_type_exists(&ty.basic, &ty.span, Keyword::STATEMENT_START);
+                    // The variable is only declared after its initializer, so
+                    // `int x = x;` refers to a field `x` (if any).
+                    if let Some(init) = init {
+                        self.visit(&NodeKind::from(&**init));
+                    }
+                    self.declare(name, VarDef::Local(stmt), &stmt.span);
+                }
+                ast::Stmt::Expression(expr) => {
+                    self.statement_expr = Some(RefEquality(&**expr));
+                    self.visit_children(node);
+                }
+                _ => self.visit_children(node),
+            },
+
+            Type(ty) => self.check_type_exists(&ty.basic, &ty.span, &[]),
+
+            Expr(expr) => {
+                self.resolve_expr(expr);
+                self.visit_children(node);
+            }
+
+            _ => self.visit_children(node),
+        }
+    }
+
+    fn visit_children(&mut self, node: &NodeKind<'a, 'f>) {
+        node.for_each_child(&mut |child| self.visit(&child));
+    }
+
+    fn resolve_expr(&mut self, expr: &'a Spanned<'f, ast::Expr<'f>>) {
+        use crate::ast::Expr::*;
+        match &expr.data {
+            Var(name) => match self.lookup_var(name) {
+                Some(def) => {
+                    if let VarDef::MainParam(_) = def {
+                        self.error(
+                            &expr.span,
+                            SemanticError::UseOfMainParameter {
+                                name: name.to_string(),
+                            },
+                        );
+                    }
+                    self.resolution.vars.insert(RefEquality(expr), def);
+                }
+                None => {
+                    let keywords = if self.statement_expr == Some(RefEquality(expr)) {
+                        Keyword::STATEMENT_START
+                    } else {
+                        &[]
+                    };
+                    self.error(
+                        &expr.span,
+                        SemanticError::UndeclaredVariable {
+                            name: name.to_string(),
+                            suggestion: self.suggest_var(name, keywords),
+                        },
+                    )
+                }
+            },
+            ThisMethodInvocation(name, _) => {
+                if let Some(class) = self.current_class {
+                    if class.method(name).is_none() {
+                        self.error(
+                            &expr.span,
+                            SemanticError::UndeclaredMethod {
+                                name: name.to_string(),
+                                class_name: class.name().to_string(),
+                                suggestion: DidYouMean::closest(
+                                    name,
+                                    class.methods.keys().map(|name| &**name),
+                                ),
+                            },
+                        );
+                    }
+                }
+            }
+            NewObject(name) => {
+                self.check_type_exists(&ast::BasicType::Custom(name.clone()), &expr.span, &[])
+            }
+            NewArray(basic, _, _) => self.check_type_exists(basic, &expr.span, &[]),
+            _ => (),
+        }
+    }
+
+    fn lookup_var(&self, name: &Symbol) -> Option<VarDef<'a, 'f>> {
+        self.locals
+            .lookup(name)
+            .cloned()
+            .or_else(|| {
+                self.current_class
+                    .and_then(|class| class.field(name))
+                    .map(VarDef::Field)
+            })
+            .or_else(|| BuiltinClass::global(name).map(VarDef::Builtin))
+    }
+
+    /// Suggest a variable, or one of `keywords`, that `name` is a typo of.
+    fn suggest_var(&self, name: &str, keywords: &[Keyword]) -> DidYouMean {
+        let locals = self.locals.symbols().map(|name| &**name);
+        let fields = self
+            .current_class
+            .into_iter()
+            .flat_map(|class| class.fields.keys())
+            .map(|name| &**name);
+        let globals = BuiltinClass::GLOBALS.iter().cloned();
+        let keywords = keywords.iter().map(|keyword| keyword.as_str());
+        DidYouMean::closest(name, locals.chain(fields).chain(globals).chain(keywords))
+    }
+
+    /// Declare a parameter or local variable in the innermost scope.
+    fn declare(&mut self, name: &Symbol, def: VarDef<'a, 'f>, span: &Span<'f>) {
+        if let Some(prev) = self.locals.lookup(name) {
+            let name = name.to_string();
+            let (error, label) = match (prev, def) {
+                (VarDef::Param(_), VarDef::Param(_)) => (
+                    SemanticError::RedefinitionOfParameter { name },
+                    "first declared here",
+                ),
+                (VarDef::Param(_), _) | (VarDef::MainParam(_), _) => (
+                    SemanticError::LocalShadowsParameter { name },
+                    "parameter declared here",
+                ),
+                _ => (
+                    SemanticError::RedefinitionOfLocal { name },
+                    "first declared here",
+                ),
+            };
+            let prev_span = match prev {
+                VarDef::Param(param) => param.span.clone(),
+                VarDef::MainParam(member) | VarDef::Field(member) => member.span.first_line(),
+                VarDef::Local(stmt) => stmt.span.clone(),
+                VarDef::Builtin(_) => unreachable!("builtins are not local variables"),
+            };
+            let error = Spanned {
+                span: span.clone(),
+                data: error,
+            };
+            self.context.diagnostics.emit_message(
+                &Message::new(MessageLevel::Error, &error)
+                    .with_code(error.code())
+                    .with_label(prev_span, label),
+            );
+            return;
+        }
+
+        self.locals.insert(name.clone(), def);
+    }
+
+    /// Report `basic` if it is undeclared, suggesting a type or one of
+    /// `keywords` that it is a typo of.
+    fn check_type_exists(&self, basic: &ast::BasicType, span: &Span<'f>, keywords: &[Keyword]) {
+        if let ast::BasicType::Custom(name) = basic {
+            if !self.classes.contains(name) && !builtins::is_builtin_type(name) {
+                self.error(
+                    span,
+                    SemanticError::UndeclaredType {
+                        name: name.to_string(),
+                        suggestion: DidYouMean::closest(
+                            name,
+                            self.classes
+                                .iter()
+                                .map(|class| &**class.name())
+                                .chain(Some(builtins::STRING))
+                                .chain(keywords.iter().map(|keyword| keyword.as_str())),
+                        ),
+                    },
+                );
+            }
+        }
+    }
+
+    fn error(&self, span: &Span<'f>, error: SemanticError) {
+        self.context.diagnostics.coded_error(&Spanned {
+            span: span.clone(),
+            data: error,
+        });
+    }
+}
@@ -0,0 +1,112 @@
+//! A symbol table with nested scopes.
+//!
+//! Every scope maps a `Symbol` to a definition of type `T`. Lookups start in
+//! the innermost scope and continue outwards, so a definition in an inner
+//! scope hides definitions of the same name in enclosing scopes.
+use crate::strtab::Symbol;
+use std::collections::HashMap;
+
+#[derive(Debug)]
+pub struct SymbolTable<T> {
+    scopes: Vec<HashMap<Symbol, T>>,
+}
+
+impl<T> Default for SymbolTable<T> {
+    fn default() -> Self {
+        Self { scopes: Vec::new() }
+    }
+}
+
+impl<T> SymbolTable<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn enter_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// Drop all definitions of the innermost scope.
+    ///
+    /// Panics if there is no open scope, since this always indicates
+    /// unbalanced calls to `enter_scope` and `leave_scope`.
+    pub fn leave_scope(&mut self) {
+        self.scopes
+            .pop()
+            .expect("Bug! leave_scope called without an open scope");
+    }
+
+    /// Define `symbol` in the innermost scope. If `symbol` is already
+    /// defined in exactly this scope, the old definition is replaced and
+    /// returned.
+    ///
+    /// Panics if there is no open scope.
+    pub fn insert(&mut self, symbol: Symbol, def: T) -> Option<T> {
+        self.scopes
+            .last_mut()
+            .expect("Bug! insert called without an open scope")
+            .insert(symbol, def)
+    }
+
+    /// Find the innermost definition of `symbol`.
+    pub fn lookup(&self, symbol: &str) -> Option<&T> {
+        self.scopes
+            .iter()
+            .rev()
+            .filter_map(|scope| scope.get(symbol))
+            .next()
+    }
+
+    /// All symbols defined in any open scope.
+    pub fn symbols(&self) -> impl Iterator<Item = &Symbol> {
+        self.scopes.iter().flat_map(|scope| scope.keys())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scopes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strtab::StringTable;
+
+    #[test]
+    fn inner_scope_hides_outer_scope() {
+        let strtab = StringTable::new();
+        let mut symtab = SymbolTable::new();
+
+        symtab.enter_scope();
+        symtab.insert(strtab.intern("x"), 1);
+        symtab.enter_scope();
+        symtab.insert(strtab.intern("x"), 2);
+        assert_eq!(symtab.lookup("x"), Some(&2));
+
+        symtab.leave_scope();
+        assert_eq!(symtab.lookup("x"), Some(&1));
+
+        symtab.leave_scope();
+        assert_eq!(symtab.lookup("x"), None);
+        assert!(symtab.is_empty());
+    }
+
+    #[test]
+    fn insert_returns_previous_definition_of_same_scope() {
+        let strtab = StringTable::new();
+        let mut symtab = SymbolTable::new();
+
+        symtab.enter_scope();
+        assert_eq!(symtab.insert(strtab.intern("x"), 1), None);
+        symtab.enter_scope();
+        assert_eq!(symtab.insert(strtab.intern("x"), 2), None);
+        assert_eq!(symtab.insert(strtab.intern("x"), 3), Some(2));
+    }
+
+    #[test]
+    #[should_panic]
+    fn unbalanced_leave_scope() {
+        let mut symtab: SymbolTable<()> = SymbolTable::new();
+        symtab.leave_scope();
+    }
+}
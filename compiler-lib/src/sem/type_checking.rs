@@ -0,0 +1,553 @@
+//! The type checker computes the type of every expression and verifies that
+//! operators, assignments, conditions, array accesses, method calls and
+//! return statements are applied to values of the correct types.
+//!
+//! The computed types are stored in `TypeAnalysis`, a side table that maps
+//! every `Expr` node of the program to its `CheckedType`, so later phases
+//! do not have to derive them again.
+use super::{
+    builtins,
+    class_table::{ClassDef, ClassTable, MemberRef},
+    name_resolution::{NameResolution, VarDef},
+    types::CheckedType,
+    SemanticError,
+};
+use crate::{
+    asciifile::{Span, Spanned},
+    ast,
+    context::Context,
+    diagnostics::{Message, MessageLevel},
+    error_codes::Coded,
+    spelling::DidYouMean,
+    strtab::Symbol,
+    utils::RefEquality,
+};
+use std::collections::HashMap;
+
+#[derive(Debug, Default)]
+pub struct TypeAnalysis<'a, 'f> {
+    types: HashMap<RefEquality<'a, Spanned<'f, ast::Expr<'f>>>, CheckedType>,
+}
+
+impl<'a, 'f> TypeAnalysis<'a, 'f> {
+    /// Get the type of an expression. Every expression of a checked program
+    /// has a type, so this only returns `None` for expressions that are not
+    /// part of the program.
+    pub fn expr_type(&self, expr: &'a Spanned<'f, ast::Expr<'f>>) -> Option<&CheckedType> {
+        self.types.get(&RefEquality(expr))
+    }
+}
+
+pub fn check<'a, 'f>(
+    program: &'a Spanned<'f, ast::Program<'f>>,
+    classes: &ClassTable<'a, 'f>,
+    names: &NameResolution<'a, 'f>,
+    context: &Context<'_>,
+) -> TypeAnalysis<'a, 'f> {
+    let mut checker = TypeChecker {
+        context,
+        classes,
+        names,
+        current_class: None,
+        return_type: CheckedType::Void,
+        return_type_span: None,
+        analysis: TypeAnalysis::default(),
+    };
+
+    for decl in &program.classes {
+        checker.current_class = classes.get(&decl.name);
+        for member in &decl.members {
+            checker.check_member(member);
+        }
+    }
+
+    checker.analysis
+}
+
+struct TypeChecker<'a, 'f, 'cx> {
+    context: &'cx Context<'cx>,
+    classes: &'cx ClassTable<'a, 'f>,
+    names: &'cx NameResolution<'a, 'f>,
+    current_class: Option<&'cx ClassDef<'a, 'f>>,
+    /// The declared return type of the method that is currently checked.
+    return_type: CheckedType,
+    /// The span of the declared return type, `None` for the main method.
+    return_type_span: Option<Span<'f>>,
+    analysis: TypeAnalysis<'a, 'f>,
+}
+
+impl<'a, 'f, 'cx> TypeChecker<'a, 'f, 'cx> {
+    fn check_member(&mut self, member: &'a Spanned<'f, ast::ClassMember<'f>>) {
+        use crate::ast::ClassMemberKind::*;
+        match &member.kind {
+            Field(ty) => self.check_value_type(ty),
+            Method(ty, params, body) => {
+                if ty.basic == ast::BasicType::Void && ty.array_depth > 0 {
+                    self.error(&ty.span, SemanticError::VoidNotAllowed);
+                }
+                for param in params.iter() {
+                    self.check_value_type(&param.ty);
+                }
+                self.return_type = self.resolve_type(ty);
+                self.return_type_span = Some(ty.span.clone());
+                self.check_block(body);
+            }
+            MainMethod(_, body) => {
+                self.return_type = CheckedType::Void;
+                self.return_type_span = None;
+                self.check_block(body);
+            }
+        }
+    }
+
+    /// Fields, parameters and local variables must not be of type `void`.
+    fn check_value_type(&self, ty: &Spanned<'f, ast::Type>) {
+        if ty.basic == ast::BasicType::Void {
+            self.error(&ty.span, SemanticError::VoidNotAllowed);
+        }
+    }
+
+    fn check_block(&mut self, block: &'a Spanned<'f, ast::Block<'f>>) {
+        for stmt in &block.statements {
+            self.check_stmt(stmt);
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &'a Spanned<'f, ast::Stmt<'f>>) {
+        use crate::ast::Stmt::*;
+        match &stmt.data {
+            Block(block) => self.check_block(block),
+            Empty | Error => (),
+            If(cond, then_arm, else_arm) => {
+                self.expect_type(cond, &CheckedType::Boolean);
+                self.check_stmt(then_arm);
+                if let Some(else_arm) = else_arm {
+                    self.check_stmt(else_arm);
+                }
+            }
+            While(cond, body) => {
+                self.expect_type(cond, &CheckedType::Boolean);
+                self.check_stmt(body);
+            }
+            Expression(expr) => {
+                self.type_of(expr);
+            }
+            Return(None) => match self.return_type {
+                CheckedType::Void | CheckedType::Unknown => (),
+                ref return_type => self.error(
+                    &stmt.span,
+                    SemanticError::MissingReturnValue {
+                        ty: return_type.to_string(),
+                    },
+                ),
+            },
+            Return(Some(expr)) => {
+                if self.return_type == CheckedType::Void {
+                    self.type_of(expr);
+                    self.error(&expr.span, SemanticError::UnexpectedReturnValue);
+                } else {
+                    let return_type = self.return_type.clone();
+                    let return_type_span = self.return_type_span.clone();
+                    self.expect_declared_type(expr, &return_type, return_type_span.as_ref());
+                }
+            }
+            LocalVariableDeclaration(ty, _, init) => {
+                self.check_value_type(ty);
+                if let Some(init) = init {
+                    let expected = self.resolve_type(ty);
+                    self.expect_declared_type(init, &expected, Some(&ty.span));
+                }
+            }
+        }
+    }
+
+    /// Compute the type of `expr` and store it in the side table.
+    fn type_of(&mut self, expr: &'a Spanned<'f, ast::Expr<'f>>) -> CheckedType {
+        let ty = self.compute_type(expr);
+        self.analysis.types.insert(RefEquality(expr), ty.clone());
+        ty
+    }
+
+    /// Check that `expr` can be assigned to a variable of type `expected`.
+    fn expect_type(&mut self, expr: &'a Spanned<'f, ast::Expr<'f>>, expected: &CheckedType) {
+        self.expect_declared_type(expr, expected, None)
+    }
+
+    /// Like `expect_type`, but a mismatch also points to `declaration`, the
+    /// type, variable or parameter that `expected` comes from.
+    fn expect_declared_type(
+        &mut self,
+        expr: &'a Spanned<'f, ast::Expr<'f>>,
+        expected: &CheckedType,
+        declaration: Option<&Span<'f>>,
+    ) {
+        let actual = self.type_of(expr);
+        if !actual.is_assignable_to(expected) {
+            let error = Spanned {
+                span: expr.span.clone(),
+                data: SemanticError::MismatchedTypes {
+                    expected: expected.to_string(),
+                    actual: actual.to_string(),
+                },
+            };
+            let mut message = Message::new(MessageLevel::Error, &error).with_code(error.code());
+            if let Some(span) = declaration {
+                message = message.with_label(span.clone(), "expected because of this");
+            }
+            self.context.diagnostics.emit_message(&message);
+        }
+    }
+
+    #[allow(clippy::cyclomatic_complexity)]
+    fn compute_type(&mut self, expr: &'a Spanned<'f, ast::Expr<'f>>) -> CheckedType {
+        use crate::ast::{BinaryOp, Expr::*, UnaryOp};
+        match &expr.data {
+            Binary(BinaryOp::Assign, lhs, rhs) => {
+                let ty = self.type_of(lhs);
+                // built-in objects like `System.out` cannot be replaced
+                if !is_lvalue(lhs) || ty.is_builtin() {
+                    self.error(&lhs.span, SemanticError::NotAssignable);
+                    self.type_of(rhs);
+                    return CheckedType::Unknown;
+                }
+                self.expect_declared_type(rhs, &ty, Some(&lhs.span));
+                ty
+            }
+            Binary(op, lhs, rhs) => match op {
+                BinaryOp::Equals | BinaryOp::NotEquals => {
+                    let lhs_ty = self.type_of(lhs);
+                    let rhs_ty = self.type_of(rhs);
+                    if lhs_ty == CheckedType::Void || !lhs_ty.is_comparable_to(&rhs_ty) {
+                        self.error(
+                            &expr.span,
+                            SemanticError::InvalidComparison {
+                                lhs: lhs_ty.to_string(),
+                                rhs: rhs_ty.to_string(),
+                            },
+                        );
+                    }
+                    CheckedType::Boolean
+                }
+                BinaryOp::LogicalOr | BinaryOp::LogicalAnd => {
+                    self.expect_type(lhs, &CheckedType::Boolean);
+                    self.expect_type(rhs, &CheckedType::Boolean);
+                    CheckedType::Boolean
+                }
+                BinaryOp::LessThan
+                | BinaryOp::GreaterThan
+                | BinaryOp::LessEquals
+                | BinaryOp::GreaterEquals => {
+                    self.expect_type(lhs, &CheckedType::Int);
+                    self.expect_type(rhs, &CheckedType::Int);
+                    CheckedType::Boolean
+                }
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
+                    self.expect_type(lhs, &CheckedType::Int);
+                    self.expect_type(rhs, &CheckedType::Int);
+                    CheckedType::Int
+                }
+                BinaryOp::Assign => unreachable!("handled above"),
+            },
+            Unary(UnaryOp::Not, operand) => {
+                self.expect_type(operand, &CheckedType::Boolean);
+                CheckedType::Boolean
+            }
+            Unary(UnaryOp::Neg, operand) => {
+                self.expect_type(operand, &CheckedType::Int);
+                CheckedType::Int
+            }
+            MethodInvocation(target, name, args) => match self.type_of(target) {
+                CheckedType::Class(class_name) => {
+                    let class = self.classes.get(&class_name);
+                    match class.and_then(|c| c.method(name)) {
+                        Some(method) => self.check_method_call(expr, method, args),
+                        None => {
+                            let names = class.into_iter().flat_map(|c| c.methods.keys());
+                            self.error(
+                                &expr.span,
+                                SemanticError::UndeclaredMethod {
+                                    name: name.to_string(),
+                                    class_name: class_name.to_string(),
+                                    suggestion: DidYouMean::closest(name, names.map(|n| &**n)),
+                                },
+                            );
+                            self.check_unknown_args(args);
+                            CheckedType::Unknown
+                        }
+                    }
+                }
+                CheckedType::Builtin(class) => match class.method(name) {
+                    Some(method) => self.check_call(
+                        expr,
+                        method.name(),
+                        &method.param_types(),
+                        &[],
+                        method.return_type(),
+                        args,
+                    ),
+                    None => {
+                        self.error(
+                            &expr.span,
+                            SemanticError::UndeclaredMethod {
+                                name: name.to_string(),
+                                class_name: class.to_string(),
+                                suggestion: DidYouMean::closest(
+                                    name,
+                                    class.method_names().iter().cloned(),
+                                ),
+                            },
+                        );
+                        self.check_unknown_args(args);
+                        CheckedType::Unknown
+                    }
+                },
+                CheckedType::Unknown => {
+                    self.check_unknown_args(args);
+                    CheckedType::Unknown
+                }
+                ty => {
+                    self.error(
+                        &target.span,
+                        SemanticError::MethodCallOnNonObject { ty: ty.to_string() },
+                    );
+                    self.check_unknown_args(args);
+                    CheckedType::Unknown
+                }
+            },
+            FieldAccess(target, name) => match self.type_of(target) {
+                CheckedType::Class(class_name) => {
+                    let class = self.classes.get(&class_name);
+                    match class.and_then(|c| c.field(name)) {
+                        Some(field) => self.member_type(field),
+                        None => {
+                            let names = class.into_iter().flat_map(|c| c.fields.keys());
+                            self.error(
+                                &expr.span,
+                                SemanticError::UndeclaredField {
+                                    name: name.to_string(),
+                                    class_name: class_name.to_string(),
+                                    suggestion: DidYouMean::closest(name, names.map(|n| &**n)),
+                                },
+                            );
+                            CheckedType::Unknown
+                        }
+                    }
+                }
+                CheckedType::Builtin(class) => match class.field(name) {
+                    Some(field) => CheckedType::Builtin(field),
+                    None => {
+                        self.error(
+                            &expr.span,
+                            SemanticError::UndeclaredField {
+                                name: name.to_string(),
+                                class_name: class.to_string(),
+                                suggestion: DidYouMean::closest(
+                                    name,
+                                    class.field_names().iter().cloned(),
+                                ),
+                            },
+                        );
+                        CheckedType::Unknown
+                    }
+                },
+                CheckedType::Unknown => CheckedType::Unknown,
+                ty => {
+                    self.error(
+                        &target.span,
+                        SemanticError::FieldAccessOnNonObject { ty: ty.to_string() },
+                    );
+                    CheckedType::Unknown
+                }
+            },
+            ArrayAccess(target, index) => {
+                let target_ty = self.type_of(target);
+                self.expect_type(index, &CheckedType::Int);
+                match target_ty {
+                    CheckedType::Array(element_ty) => *element_ty,
+                    CheckedType::Unknown => CheckedType::Unknown,
+                    ty => {
+                        self.error(
+                            &target.span,
+                            SemanticError::IndexOnNonArray { ty: ty.to_string() },
+                        );
+                        CheckedType::Unknown
+                    }
+                }
+            }
+            Null => CheckedType::Null,
+            Boolean(_) => CheckedType::Boolean,
+            Int(_) => CheckedType::Int,
+            Var(_) => match self.names.var_def(expr) {
+                Some(def) => self.var_type(def),
+                // already reported by name resolution
+                None => CheckedType::Unknown,
+            },
+            ThisMethodInvocation(name, args) => {
+                match self.current_class.and_then(|class| class.method(name)) {
+                    Some(method) => self.check_method_call(expr, method, args),
+                    None => {
+                        // already reported by name resolution
+                        self.check_unknown_args(args);
+                        CheckedType::Unknown
+                    }
+                }
+            }
+            This => self.current_class.map_or(CheckedType::Unknown, |class| {
+                CheckedType::Class(class.name().clone())
+            }),
+            NewObject(name) => {
+                if builtins::is_builtin_type(name) {
+                    self.error(
+                        &expr.span,
+                        SemanticError::InstantiationOfBuiltinClass {
+                            name: name.to_string(),
+                        },
+                    );
+                }
+                self.resolve_type(&ast::Type {
+                    basic: ast::BasicType::Custom(name.clone()),
+                    array_depth: 0,
+                })
+            }
+            NewArray(basic, size, depth) => {
+                self.expect_type(size, &CheckedType::Int);
+                if *basic == ast::BasicType::Void {
+                    self.error(&expr.span, SemanticError::VoidNotAllowed);
+                    return CheckedType::Unknown;
+                }
+                self.resolve_type(&ast::Type {
+                    basic: basic.clone(),
+                    array_depth: depth + 1,
+                })
+            }
+        }
+    }
+
+    /// Check a call of a method declared in the program and return its
+    /// return type.
+    fn check_method_call(
+        &mut self,
+        call: &'a Spanned<'f, ast::Expr<'f>>,
+        method: MemberRef<'a, 'f>,
+        args: &'a Spanned<'f, ast::ArgumentList<'f>>,
+    ) -> CheckedType {
+        match &method.kind {
+            ast::ClassMemberKind::Method(return_type, params, _) => {
+                let param_types: Vec<_> = params
+                    .iter()
+                    .map(|param| self.resolve_type(&param.ty))
+                    .collect();
+                let param_spans: Vec<_> =
+                    params.iter().map(|param| param.ty.span.clone()).collect();
+                let return_type = self.resolve_type(return_type);
+                self.check_call(
+                    call,
+                    &method.name,
+                    &param_types,
+                    &param_spans,
+                    return_type,
+                    args,
+                )
+            }
+            // the main method can only be found by name resolution, but it
+            // has no parameters that can be passed from MiniJava
+            _ => {
+                self.check_unknown_args(args);
+                CheckedType::Void
+            }
+        }
+    }
+
+    /// Check the arguments of a call to a method with the given signature
+    /// and return `return_type`. The spans of the parameter types are only
+    /// known for methods declared in the program.
+    fn check_call(
+        &mut self,
+        call: &'a Spanned<'f, ast::Expr<'f>>,
+        name: &str,
+        param_types: &[CheckedType],
+        param_spans: &[Span<'f>],
+        return_type: CheckedType,
+        args: &'a Spanned<'f, ast::ArgumentList<'f>>,
+    ) -> CheckedType {
+        if param_types.len() != args.len() {
+            self.error(
+                &call.span,
+                SemanticError::WrongNumberOfArguments {
+                    name: name.to_string(),
+                    expected: param_types.len(),
+                    actual: args.len(),
+                },
+            );
+        }
+
+        for (index, (arg, param_ty)) in args.iter().zip(param_types).enumerate() {
+            self.expect_declared_type(arg, param_ty, param_spans.get(index));
+        }
+        // type surplus arguments, too
+        for arg in args.iter().skip(param_types.len()) {
+            self.type_of(arg);
+        }
+
+        return_type
+    }
+
+    /// Compute the types of arguments passed to a method that does not
+    /// exist.
+    fn check_unknown_args(&mut self, args: &'a Spanned<'f, ast::ArgumentList<'f>>) {
+        for arg in args.iter() {
+            self.type_of(arg);
+        }
+    }
+
+    fn var_type(&self, def: VarDef<'a, 'f>) -> CheckedType {
+        match def {
+            VarDef::Field(member) => self.member_type(member),
+            VarDef::Param(param) => self.resolve_type(&param.ty),
+            VarDef::MainParam(_) => CheckedType::Class(Symbol::from(builtins::STRING)).array_of(1),
+            VarDef::Local(stmt) => match &stmt.data {
+                ast::Stmt::LocalVariableDeclaration(ty, _, _) => self.resolve_type(ty),
+                _ => unreachable!("locals are only defined by local variable declarations"),
+            },
+            VarDef::Builtin(class) => CheckedType::Builtin(class),
+        }
+    }
+
+    fn member_type(&self, member: MemberRef<'a, 'f>) -> CheckedType {
+        match &member.kind {
+            ast::ClassMemberKind::Field(ty) | ast::ClassMemberKind::Method(ty, _, _) => {
+                self.resolve_type(ty)
+            }
+            ast::ClassMemberKind::MainMethod(..) => CheckedType::Void,
+        }
+    }
+
+    /// Convert an `ast::Type` into a `CheckedType`. Types that refer to
+    /// undeclared classes become `CheckedType::Unknown`, name resolution
+    /// has already reported them.
+    fn resolve_type(&self, ty: &ast::Type) -> CheckedType {
+        match &ty.basic {
+            ast::BasicType::Custom(name)
+                if !self.classes.contains(name) && !builtins::is_builtin_type(name) =>
+            {
+                CheckedType::Unknown
+            }
+            _ => CheckedType::from(ty),
+        }
+    }
+
+    fn error(&self, span: &Span<'f>, error: SemanticError) {
+        self.context.diagnostics.coded_error(&Spanned {
+            span: span.clone(),
+            data: error,
+        });
+    }
+}
+
+/// Only variables, fields and array elements can be assigned to.
+fn is_lvalue(expr: &ast::Expr<'_>) -> bool {
+    match expr {
+        ast::Expr::Var(_) | ast::Expr::FieldAccess(..) | ast::Expr::ArrayAccess(..) => true,
+        _ => false,
+    }
+}
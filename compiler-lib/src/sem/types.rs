@@ -0,0 +1,132 @@
+use super::builtins::BuiltinClass;
+use crate::{ast, strtab::Symbol};
+use std::fmt;
+
+/// The type of a MiniJava expression as computed by the type checker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckedType {
+    Int,
+    Boolean,
+    Void,
+    /// The type of the `null` literal. It can be assigned to every
+    /// reference type, i.e. classes and arrays.
+    Null,
+    Class(Symbol),
+    Array(Box<CheckedType>),
+    /// One of the pseudo-classes of the prelude, e.g. the type of
+    /// `System.out`. Values of these types cannot be stored anywhere, they
+    /// can only be used to access their members.
+    Builtin(BuiltinClass),
+    /// The type of an expression whose type could not be determined
+    /// because of a previous error. It is compatible with every other type
+    /// to avoid reporting follow-up errors.
+    Unknown,
+}
+
+impl CheckedType {
+    /// Wrap `self` in `depth` array types.
+    pub fn array_of(self, depth: u64) -> CheckedType {
+        (0..depth).fold(self, |ty, _| CheckedType::Array(box ty))
+    }
+
+    pub fn is_reference(&self) -> bool {
+        match self {
+            CheckedType::Null | CheckedType::Class(_) | CheckedType::Array(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_builtin(&self) -> bool {
+        match self {
+            CheckedType::Builtin(_) => true,
+            _ => false,
+        }
+    }
+
+    /// True if a value of type `self` may be stored in a variable of type
+    /// `target`.
+    pub fn is_assignable_to(&self, target: &CheckedType) -> bool {
+        match (self, target) {
+            (CheckedType::Unknown, _) | (_, CheckedType::Unknown) => true,
+            (CheckedType::Void, _) | (_, CheckedType::Void) => false,
+            (CheckedType::Builtin(_), _) | (_, CheckedType::Builtin(_)) => false,
+            (CheckedType::Null, target) => target.is_reference() && *target != CheckedType::Null,
+            (ty, target) => ty == target,
+        }
+    }
+
+    /// True if values of the two types may be compared using `==` and `!=`.
+    pub fn is_comparable_to(&self, other: &CheckedType) -> bool {
+        match (self, other) {
+            (CheckedType::Null, CheckedType::Null) => true,
+            _ => self.is_assignable_to(other) || other.is_assignable_to(self),
+        }
+    }
+}
+
+impl From<&ast::BasicType> for CheckedType {
+    fn from(basic: &ast::BasicType) -> Self {
+        match basic {
+            ast::BasicType::Int => CheckedType::Int,
+            ast::BasicType::Boolean => CheckedType::Boolean,
+            ast::BasicType::Void => CheckedType::Void,
+            ast::BasicType::Custom(name) => CheckedType::Class(name.clone()),
+        }
+    }
+}
+
+impl From<&ast::Type> for CheckedType {
+    fn from(ty: &ast::Type) -> Self {
+        CheckedType::from(&ty.basic).array_of(ty.array_depth)
+    }
+}
+
+impl fmt::Display for CheckedType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckedType::Int => write!(f, "int"),
+            CheckedType::Boolean => write!(f, "boolean"),
+            CheckedType::Void => write!(f, "void"),
+            CheckedType::Null => write!(f, "null"),
+            CheckedType::Class(name) => write!(f, "{}", name),
+            CheckedType::Array(inner) => write!(f, "{}[]", inner),
+            CheckedType::Builtin(class) => write!(f, "{}", class),
+            CheckedType::Unknown => write!(f, "{{unknown}}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strtab::StringTable;
+
+    #[test]
+    fn null_is_assignable_to_references_only() {
+        let strtab = StringTable::new();
+        let class = CheckedType::Class(strtab.intern("Foo"));
+
+        assert!(CheckedType::Null.is_assignable_to(&class));
+        assert!(CheckedType::Null.is_assignable_to(&CheckedType::Int.array_of(2)));
+        assert!(!CheckedType::Null.is_assignable_to(&CheckedType::Int));
+        assert!(!CheckedType::Null.is_assignable_to(&CheckedType::Null));
+        assert!(!class.is_assignable_to(&CheckedType::Null));
+        assert!(CheckedType::Null.is_comparable_to(&CheckedType::Null));
+        assert!(class.is_comparable_to(&CheckedType::Null));
+    }
+
+    #[test]
+    fn void_is_never_assignable() {
+        assert!(!CheckedType::Void.is_assignable_to(&CheckedType::Void));
+        assert!(!CheckedType::Int.is_assignable_to(&CheckedType::Void));
+    }
+
+    #[test]
+    fn array_types_must_match_exactly() {
+        let ints = CheckedType::Int.array_of(1);
+        assert!(ints.is_assignable_to(&CheckedType::Int.array_of(1)));
+        assert!(!ints.is_assignable_to(&CheckedType::Int.array_of(2)));
+        assert!(!ints.is_assignable_to(&CheckedType::Boolean.array_of(1)));
+        assert_eq!(CheckedType::Int.array_of(2).to_string(), "int[][]");
+    }
+}
@@ -0,0 +1,117 @@
+//! "Did you mean" suggestions for misspelled names and keywords.
+//!
+//! A suggestion is the candidate with the smallest edit distance to the
+//! misspelled name. Candidates that are too far away are not suggested,
+//! and neither are candidates that would replace every character of the
+//! name, since e.g. `x` is hardly a typo of `y`.
+use std::fmt;
+
+/// The edit distance between `a` and `b`, i.e. the number of inserted,
+/// removed, replaced or swapped adjacent characters needed to turn `a` into
+/// `b`. Swapped characters are counted as one edit, since they are a common
+/// typo.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // distances[i][j] is the distance between the first `i` characters of
+    // `a` and the first `j` characters of `b`
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for j in 0..=b.len() {
+        distances[0][j] = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let replace = distances[i - 1][j - 1] + if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut distance = replace
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+/// The candidate closest to `name`, if it is close enough to be a likely
+/// typo. Ties are broken alphabetically, so the result does not depend on
+/// the order of the candidates.
+pub fn closest_match<'c, I>(name: &str, candidates: I) -> Option<&'c str>
+where
+    I: IntoIterator<Item = &'c str>,
+{
+    let len = name.chars().count();
+    let max_distance = (len / 3).max(1);
+
+    let mut candidates: Vec<&str> = candidates
+        .into_iter()
+        .filter(|&candidate| candidate != name)
+        .collect();
+    candidates.sort();
+
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= max_distance && distance < len)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// An optional suggestion that is appended to an error message.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DidYouMean(pub Option<String>);
+
+impl DidYouMean {
+    pub fn closest<'c, I>(name: &str, candidates: I) -> Self
+    where
+        I: IntoIterator<Item = &'c str>,
+    {
+        DidYouMean(closest_match(name, candidates).map(|name| format!("'{}'", name)))
+    }
+}
+
+impl fmt::Display for DidYouMean {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Some(suggestion) => write!(f, "; did you mean {}?", suggestion),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distance_counts_edits() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("abc", ""), 3);
+        assert_eq!(edit_distance("pubic", "public"), 1);
+        assert_eq!(edit_distance("retrun", "return"), 1);
+        assert_eq!(edit_distance("ab", "ba"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn only_close_candidates_are_suggested() {
+        let keywords = vec!["public", "return", "static", "void"];
+        assert_eq!(closest_match("pubic", keywords.clone()), Some("public"));
+        assert_eq!(closest_match("retrun", keywords.clone()), Some("return"));
+        assert_eq!(closest_match("xs", vec!["y", "x"]), Some("x"));
+        assert_eq!(closest_match("x", vec!["y", "xs"]), None);
+        assert_eq!(closest_match("foo", keywords), None);
+    }
+
+    #[test]
+    fn ties_are_broken_alphabetically() {
+        assert_eq!(closest_match("ab", vec!["bb", "aa"]), Some("aa"));
+        assert_eq!(closest_match("ab", vec!["aa", "bb"]), Some("aa"));
+    }
+}
@@ -1,3 +1,5 @@
+use std::hash::{Hash, Hasher};
+
 macro_rules! matches {
     ($expression: expr, $( $pattern: pat )|*) => {{
         match $expression {
@@ -79,3 +81,33 @@ where
         Some(self.buffer.remove(0))
     }
 }
+
+/// Wraps a reference so that it is compared and hashed by address instead of
+/// by value. This allows using AST nodes as keys of side tables, which is
+/// necessary because `Spanned` only compares its `data` and two structurally
+/// equal expressions at different locations in the program would otherwise
+/// collide.
+#[derive(Debug)]
+pub struct RefEquality<'a, T>(pub &'a T);
+
+impl<'a, T> Clone for RefEquality<'a, T> {
+    fn clone(&self) -> Self {
+        RefEquality(self.0)
+    }
+}
+
+impl<'a, T> Copy for RefEquality<'a, T> {}
+
+impl<'a, T> PartialEq for RefEquality<'a, T> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+
+impl<'a, T> Eq for RefEquality<'a, T> {}
+
+impl<'a, T> Hash for RefEquality<'a, T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.0 as *const T).hash(state)
+    }
+}
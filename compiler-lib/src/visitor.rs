@@ -126,7 +126,7 @@ impl<'a, 't> NodeKind<'a, 't> {
             Stmt(s) => {
                 use crate::ast::Stmt::*;
                 match &s.data {
-                    Empty => None,
+                    Empty | Error => None,
                     Block(b) => ccb!(b),
                     Expression(e) => ccb!(e.as_ref()),
                     If(expr, then_stmt, else_stmt) => {